@@ -70,6 +70,13 @@ pub(super) struct AppWindowControls {
     pub(super) import_file_input: nwg::TextInput,
     pub(super) import_file_button: nwg::Button,
     pub(super) import_file_chooser: nwg::FileDialog,
+    pub(super) import_passphrase_label: nwg::Label,
+    pub(super) import_passphrase_input: nwg::TextInput,
+    pub(super) import_passphrase_button: nwg::Button,
+    pub(super) import_key_file_label: nwg::Label,
+    pub(super) import_key_file_input: nwg::TextInput,
+    pub(super) import_key_file_button: nwg::Button,
+    pub(super) import_key_file_chooser: nwg::FileDialog,
     pub(super) import_run_button: nwg::Button,
     pub(super) import_close_button: nwg::Button,
 
@@ -106,7 +113,7 @@ impl ui::Controls for AppWindowControls {
             .build(&mut self.icon)?;
 
         nwg::Window::builder()
-            .size((520, 480))
+            .size((520, 540))
             .icon(Some(&self.icon))
             .center(true)
             .title("WiltonDB Data Transfer Tool")
@@ -399,6 +406,45 @@ impl ui::Controls for AppWindowControls {
             .title("Choose import file")
             .action(nwg::FileDialogAction::Open)
             .build(&mut self.import_file_chooser)?;
+        nwg::Label::builder()
+            .parent(&self.import_tab)
+            .text("Passphrase:")
+            .font(Some(&self.font_normal))
+            .background_color(Some(COLOR_WHITE))
+            .h_align(nwg::HTextAlign::Left)
+            .build(&mut self.import_passphrase_label)?;
+        nwg::TextInput::builder()
+            .parent(&self.import_tab)
+            .font(Some(&self.font_normal))
+            .password(Some('*'))
+            .placeholder_text(Some("Only for encrypted files"))
+            .build(&mut self.import_passphrase_input)?;
+        nwg::Button::builder()
+            .parent(&self.import_tab)
+            .text("Reload")
+            .font(Some(&self.font_normal))
+            .build(&mut self.import_passphrase_button)?;
+        nwg::Label::builder()
+            .parent(&self.import_tab)
+            .text("Key file:")
+            .font(Some(&self.font_normal))
+            .background_color(Some(COLOR_WHITE))
+            .h_align(nwg::HTextAlign::Left)
+            .build(&mut self.import_key_file_label)?;
+        nwg::TextInput::builder()
+            .parent(&self.import_tab)
+            .font(Some(&self.font_normal))
+            .placeholder_text(Some("Used instead of a passphrase"))
+            .build(&mut self.import_key_file_input)?;
+        nwg::Button::builder()
+            .parent(&self.import_tab)
+            .text("Choose")
+            .font(Some(&self.font_normal))
+            .build(&mut self.import_key_file_button)?;
+        nwg::FileDialog::builder()
+            .title("Choose key file")
+            .action(nwg::FileDialogAction::Open)
+            .build(&mut self.import_key_file_chooser)?;
 
         // import buttons
 
@@ -470,6 +516,10 @@ impl ui::Controls for AppWindowControls {
             .control(&self.import_dbnames_combo)
             .control(&self.import_file_input)
             .control(&self.import_file_button)
+            .control(&self.import_passphrase_input)
+            .control(&self.import_passphrase_button)
+            .control(&self.import_key_file_input)
+            .control(&self.import_key_file_button)
             .control(&self.import_run_button)
             .control(&self.import_close_button)
             .build();
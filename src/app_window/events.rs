@@ -176,6 +176,16 @@ impl ui::Events<AppWindowControls> for AppWindowEvents {
             .event(nwg::Event::OnButtonClick)
             .handler(AppWindow::on_choose_import_file)
             .build(&mut self.events)?;
+        ui::event_builder()
+            .control(&c.import_passphrase_button)
+            .event(nwg::Event::OnButtonClick)
+            .handler(AppWindow::on_reload_import_file)
+            .build(&mut self.events)?;
+        ui::event_builder()
+            .control(&c.import_key_file_button)
+            .event(nwg::Event::OnButtonClick)
+            .handler(AppWindow::on_choose_import_key_file)
+            .build(&mut self.events)?;
         ui::event_builder()
             .control(&c.import_run_button)
             .event(nwg::Event::OnButtonClick)
@@ -33,6 +33,8 @@ pub(super) struct AppWindowLayout {
     import_tables_view_layout: nwg::FlexboxLayout,
     import_dbnames_layout: nwg::FlexboxLayout,
     import_file_layout: nwg::FlexboxLayout,
+    import_passphrase_layout: nwg::FlexboxLayout,
+    import_key_file_layout: nwg::FlexboxLayout,
     import_buttons_layout: nwg::FlexboxLayout,
 }
 
@@ -286,6 +288,54 @@ impl ui::Layout<AppWindowControls> for AppWindowLayout {
                 .build())
             .build_partial(&self.import_file_layout)?;
 
+        nwg::FlexboxLayout::builder()
+            .parent(&c.import_tab)
+            .flex_direction(ui::FlexDirection::Row)
+            .auto_spacing(None)
+            .child(&c.import_passphrase_label)
+            .child_size(ui::size_builder()
+                .width_label_normal()
+                .height_input_form_row()
+                .build())
+            .child(&c.import_passphrase_input)
+            .child_margin(ui::margin_builder()
+                .start_pt(5)
+                .build())
+            .child_flex_grow(1.0)
+            .child(&c.import_passphrase_button)
+            .child_size(ui::size_builder()
+                .width_button_normal()
+                .height_button()
+                .build())
+            .child_margin(ui::margin_builder()
+                .start_pt(5)
+                .build())
+            .build_partial(&self.import_passphrase_layout)?;
+
+        nwg::FlexboxLayout::builder()
+            .parent(&c.import_tab)
+            .flex_direction(ui::FlexDirection::Row)
+            .auto_spacing(None)
+            .child(&c.import_key_file_label)
+            .child_size(ui::size_builder()
+                .width_label_normal()
+                .height_input_form_row()
+                .build())
+            .child(&c.import_key_file_input)
+            .child_margin(ui::margin_builder()
+                .start_pt(5)
+                .build())
+            .child_flex_grow(1.0)
+            .child(&c.import_key_file_button)
+            .child_size(ui::size_builder()
+                .width_button_normal()
+                .height_button()
+                .build())
+            .child_margin(ui::margin_builder()
+                .start_pt(5)
+                .build())
+            .build_partial(&self.import_key_file_layout)?;
+
         nwg::FlexboxLayout::builder()
             .parent(&c.import_tab)
             .flex_direction(ui::FlexDirection::Row)
@@ -318,6 +368,8 @@ impl ui::Layout<AppWindowControls> for AppWindowLayout {
             .child_flex_grow(1.0)
             .child_layout(&self.import_dbnames_layout)
             .child_layout(&self.import_file_layout)
+            .child_layout(&self.import_passphrase_layout)
+            .child_layout(&self.import_key_file_layout)
             .child_layout(&self.import_buttons_layout)
             .build(&self.import_tab_layout)?;
 
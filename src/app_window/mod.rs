@@ -29,9 +29,11 @@ use ui::PopupDialog;
 
 use about_dialog::AboutDialog;
 use about_dialog::AboutDialogArgs;
+use common::EncryptionSecret;
 use common::TableWithRowsCount;
 use common::TableWithSize;
 use common::TdsConnConfig;
+use common::TransferError;
 use connect_dialog::ConnectDialog;
 use connect_dialog::ConnectDialogArgs;
 use connect_dialog::ConnectDialogResult;
@@ -29,6 +29,7 @@ pub struct AppWindow {
     pub(super) c: AppWindowControls,
 
     conn_config: TdsConnConfig,
+    bcp_available: bool,
 
     export_tables: Vec<TableWithRowsCount>,
     import_tables: Vec<TableWithSize>,
@@ -60,11 +61,8 @@ impl AppWindow {
 
         self.set_status_bar_dbconn_label("none");
 
-        if self.check_bcp_runnable() {
-            self.open_connect_dialog(nwg::EventData::NoData);
-        } else {
-            self.close(nwg::EventData::NoData);
-        }
+        self.bcp_available = self.check_bcp_runnable();
+        self.open_connect_dialog(nwg::EventData::NoData);
     }
 
     pub(super) fn close(&mut self, _: nwg::EventData) {
@@ -167,7 +165,7 @@ impl AppWindow {
         if go_on {
             self.c.window.set_enabled(false);
             let args = ExportDialogArgs::new(
-                &self.c.export_notice, &self.conn_config,  &dbname, &tables, &dir, &filename);
+                &self.c.export_notice, &self.conn_config,  &dbname, &tables, &dir, &filename, !self.bcp_available);
             self.export_dialog_join_handle = ExportDialog::popup(args);
         }
     }
@@ -191,6 +189,13 @@ impl AppWindow {
             .filter(|t| t.import)
             .map(|t| t.clone())
             .collect();
+        let encryption = match self.import_encryption_secret() {
+            Ok(secret) => secret,
+            Err(e) => {
+                ui::message_box_error(&e.to_string());
+                return;
+            }
+        };
         let file_path_st = self.c.import_file_input.text();
         let file_path = Path::new(&file_path_st);
         let dir_path = file_path.with_extension("");
@@ -203,7 +208,7 @@ impl AppWindow {
         if go_on {
             self.c.window.set_enabled(false);
             let args = ImportDialogArgs::new(
-                &self.c.import_notice, &self.conn_config,  &dbname, &tables, &file_path_st, &dir_path_st);
+                &self.c.import_notice, &self.conn_config,  &dbname, &tables, &file_path_st, &dir_path_st, !self.bcp_available, &encryption);
             self.import_dialog_join_handle = ImportDialog::popup(args);
         }
     }
@@ -342,6 +347,24 @@ impl AppWindow {
         }
     }
 
+    pub(super) fn on_reload_import_file(&mut self, _: nwg::EventData) {
+        if !self.c.import_file_input.text().is_empty() {
+            self.load_import_file_entries();
+            self.update_import_run_button_state();
+        }
+    }
+
+    pub(super) fn on_choose_import_key_file(&mut self, ed: nwg::EventData) {
+        if self.c.import_key_file_chooser.run(Some(&self.c.window)) {
+            self.c.import_key_file_input.set_text("");
+            if let Ok(file) = self.c.import_key_file_chooser.get_selected_item() {
+                let fpath_st = file.to_string_lossy().to_string();
+                self.c.import_key_file_input.set_text(&fpath_st);
+                self.on_reload_import_file(ed);
+            }
+        }
+    }
+
     pub(super) fn on_import_tables_view_sort(&mut self, ed: nwg::EventData) {
         let col_idx = if let nwg::EventData::OnListViewItemIndex
         { column_index: col_idx, .. } = ed {
@@ -589,7 +612,14 @@ impl AppWindow {
     fn load_import_file_entries(&mut self) {
         let file_path = self.c.import_file_input.text();
         let progress_fun = |_: &str| { };
-        let tables =  match common::load_tables_from_file(&progress_fun, &file_path) {
+        let encryption = match self.import_encryption_secret() {
+            Ok(secret) => secret,
+            Err(e) => {
+                ui::message_box_error(&e.to_string());
+                return;
+            }
+        };
+        let tables =  match common::load_tables_from_file(&progress_fun, &file_path, &encryption) {
             Ok(tables) => tables,
             Err(e) => {
                 ui::message_box_error(&e.to_string());
@@ -602,6 +632,15 @@ impl AppWindow {
         self.reload_import_tables_view();
     }
 
+    // key file takes precedence over the passphrase
+    fn import_encryption_secret(&self) -> Result<EncryptionSecret, TransferError> {
+        let key_file = self.c.import_key_file_input.text();
+        if !key_file.is_empty() {
+            return EncryptionSecret::from_key_file(&key_file);
+        }
+        Ok(EncryptionSecret::from_passphrase(&self.c.import_passphrase_input.text()))
+    }
+
     fn import_table_matches_filters(&self, rec: &TableWithSize) -> bool {
         let filter = self.c.import_tables_filter_input.text();
         if 0 == filter.len() {
@@ -765,11 +804,6 @@ impl AppWindow {
                 }
             }
         }
-        if !success {
-            let proceed = ui::message_box_warning_yn(&format!(
-                "BCP utility not found, please install it (including required dependencies) and add 'bcp.exe' to system PATH environment variable.\r\n\r\nWould you like to proceed?"));
-            return proceed;
-        }
-        true
+        success
     }
 }
@@ -0,0 +1,340 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use sha2::Digest;
+use sha2::Sha256;
+use zip::CompressionMethod;
+use zip::ZipArchive;
+use zip::ZipWriter;
+use zip::write::FileOptions;
+
+// counts the bytes written into the current ZIP entry
+struct ZipEntrySink<'a> {
+    zip: &'a mut ZipWriter<File>,
+    size: u64,
+}
+
+impl<'a> Write for ZipEntrySink<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.zip.write(buf)?;
+        self.size += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.zip.flush()
+    }
+}
+
+enum EntryStream<'a> {
+    Plain(ZipEntrySink<'a>),
+    Encrypted(EncryptingWriter<ZipEntrySink<'a>>),
+}
+
+// entry is written while its contents are produced, the export file is left
+// broken until the entry is finished
+struct EntryWriter<'a> {
+    stream: EntryStream<'a>,
+    hasher: Sha256,
+    broken: &'a mut bool,
+}
+
+impl<'a> EntryWriter<'a> {
+    // returns the size of the entry and SHA-256 of the written data
+    fn finish(self) -> Result<(u64, String), TransferError> {
+        let sink = match self.stream {
+            EntryStream::Plain(sink) => sink,
+            EntryStream::Encrypted(writer) => writer.finish()?
+        };
+        *self.broken = false;
+        Ok((sink.size, format!("{:x}", self.hasher.finalize())))
+    }
+}
+
+impl<'a> Write for EntryWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = match &mut self.stream {
+            EntryStream::Plain(sink) => sink.write(buf)?,
+            EntryStream::Encrypted(writer) => writer.write(buf)?
+        };
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.stream {
+            EntryStream::Plain(sink) => sink.flush(),
+            EntryStream::Encrypted(writer) => writer.flush()
+        }
+    }
+}
+
+enum SpoolStream {
+    Plain(BufWriter<File>),
+    Encrypted(EncryptingWriter<BufWriter<File>>),
+}
+
+// entry contents are written into a temporary file next to the '.part' file without
+// holding the export file, and are copied into the entry as is with 'add_spool',
+// spooled data is encrypted the same way as the entry, so no plain data is left on disk
+pub struct SpoolWriter {
+    path: PathBuf,
+    stream: SpoolStream,
+    hasher: Sha256,
+}
+
+impl SpoolWriter {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // returns the size of the spool file and SHA-256 of the written data
+    pub fn finish(self) -> Result<(u64, String), TransferError> {
+        let mut writer = match self.stream {
+            SpoolStream::Plain(writer) => writer,
+            SpoolStream::Encrypted(writer) => writer.finish()?
+        };
+        writer.flush()?;
+        std::mem::drop(writer);
+        Ok((fs::metadata(&self.path)?.len(), format!("{:x}", self.hasher.finalize())))
+    }
+
+    pub fn abort(self) -> Result<(), TransferError> {
+        std::mem::drop(self.stream);
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+impl Write for SpoolWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = match &mut self.stream {
+            SpoolStream::Plain(writer) => writer.write(buf)?,
+            SpoolStream::Encrypted(writer) => writer.write(buf)?
+        };
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.stream {
+            SpoolStream::Plain(writer) => writer.flush(),
+            SpoolStream::Encrypted(writer) => writer.flush()
+        }
+    }
+}
+
+pub struct ArchiveWriter {
+    zip: ZipWriter<File>,
+    dirname: String,
+    part_path: PathBuf,
+    dest_path: PathBuf,
+    broken: bool,
+    key: Option<EncryptionKey>,
+}
+
+impl ArchiveWriter {
+    pub fn part_path(dest_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.part", dest_path.to_string_lossy()))
+    }
+
+    // entries are written into a '.part' file that is renamed on 'finish'
+    pub fn create(dest_path: &Path, dirname: &str) -> Result<Self, TransferError> {
+        let part_path = Self::part_path(dest_path);
+        let file = File::create(&part_path)?;
+        let mut zip = ZipWriter::new(file);
+        zip.add_directory(dirname, FileOptions::default())?;
+        Ok(Self {
+            zip,
+            dirname: dirname.to_string(),
+            part_path,
+            dest_path: dest_path.to_path_buf(),
+            broken: false,
+            key: None
+        })
+    }
+
+    // reopens a '.part' file left by 'close_partial', returns sizes of existing entries
+    pub fn reopen(dest_path: &Path, dirname: &str) -> Result<(Self, HashMap<String, u64>), TransferError> {
+        let part_path = Self::part_path(dest_path);
+        let mut sizes = HashMap::new();
+        {
+            let mut zip = ZipArchive::new(BufReader::new(File::open(&part_path)?))?;
+            let prefix = format!("{}/", dirname);
+            for i in 0..zip.len() {
+                let entry = zip.by_index(i)?;
+                if let Some(filename) = entry.name().strip_prefix(&prefix) {
+                    if !filename.is_empty() {
+                        sizes.insert(filename.to_string(), entry.size());
+                    }
+                }
+            }
+        }
+        let file = OpenOptions::new().read(true).write(true).open(&part_path)?;
+        let zip = ZipWriter::new_append(file)?;
+        Ok((Self {
+            zip,
+            dirname: dirname.to_string(),
+            part_path,
+            dest_path: dest_path.to_path_buf(),
+            broken: false,
+            key: None
+        }, sizes))
+    }
+
+    // returns checksums of the entries in a '.part' file, computed the same way
+    // as they are recorded in the journal, must be called before 'reopen'
+    pub fn part_sha256(dest_path: &Path, dirname: &str, key: Option<&EncryptionKey>) -> Result<HashMap<String, String>, TransferError> {
+        let mut zip = ZipArchive::new(BufReader::new(File::open(Self::part_path(dest_path))?))?;
+        let prefix = format!("{}/", dirname);
+        let mut checksums = HashMap::new();
+        for i in 0..zip.len() {
+            let entry = zip.by_index(i)?;
+            let entry_name = entry.name().to_string();
+            let filename = match entry_name.strip_prefix(&prefix) {
+                Some(filename) if !filename.is_empty() => filename.to_string(),
+                _ => continue
+            };
+            let entry_key = if ENCRYPTION_FILENAME == filename { None } else { key };
+            let sha256 = entry_sha256(decrypting_reader(entry, entry_key, &entry_name), data_file_codec(&filename))?;
+            checksums.insert(filename, sha256);
+        }
+        Ok(checksums)
+    }
+
+    // reads a text entry from a '.part' file, must be called before 'reopen'
+    pub fn read_part_text(dest_path: &Path, dirname: &str, filename: &str, key: Option<&EncryptionKey>) -> Result<String, TransferError> {
+        let mut zip = ZipArchive::new(BufReader::new(File::open(Self::part_path(dest_path))?))?;
+        let entry_name = format!("{}/{}", dirname, filename);
+        let entry = zip.by_name(&entry_name)?;
+        let mut text = String::new();
+        decrypting_reader(entry, key, &entry_name).read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    // entries added after this call are encrypted
+    pub fn set_key(&mut self, key: EncryptionKey) {
+        self.key = Some(key);
+    }
+
+    // data files are already compressed, so they are stored as is
+    fn open_entry(&mut self, filename: &str, large_file: bool) -> Result<EntryWriter<'_>, TransferError> {
+        let entry_name = format!("{}/{}", &self.dirname, filename);
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(large_file);
+        self.broken = true;
+        self.zip.start_file(entry_name.as_str(), options)?;
+        let sink = ZipEntrySink {
+            zip: &mut self.zip,
+            size: 0
+        };
+        let stream = match &self.key {
+            Some(key) => EntryStream::Encrypted(EncryptingWriter::new(sink, key, &entry_name)?),
+            None => EntryStream::Plain(sink)
+        };
+        Ok(EntryWriter {
+            stream,
+            hasher: Sha256::new(),
+            broken: &mut self.broken
+        })
+    }
+
+    // spool file name is prefixed with the '.part' file name, so exports
+    // into the same directory do not clash
+    pub fn create_spool(&self, filename: &str) -> Result<SpoolWriter, TransferError> {
+        let entry_name = format!("{}/{}", &self.dirname, filename);
+        let path = PathBuf::from(format!("{}.{}.spool", self.part_path.to_string_lossy(), filename));
+        let writer = BufWriter::new(File::create(&path)?);
+        let stream = match &self.key {
+            Some(key) => SpoolStream::Encrypted(EncryptingWriter::new(writer, key, &entry_name)?),
+            None => SpoolStream::Plain(writer)
+        };
+        Ok(SpoolWriter {
+            path,
+            stream,
+            hasher: Sha256::new()
+        })
+    }
+
+    // copies a finished spool file into the entry, returns the size of the entry
+    pub fn add_spool(&mut self, filename: &str, spool_path: &Path) -> Result<u64, TransferError> {
+        let entry_name = format!("{}/{}", &self.dirname, filename);
+        let spool_len = fs::metadata(spool_path)?.len();
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(spool_len >= u32::MAX as u64);
+        self.broken = true;
+        self.zip.start_file(entry_name.as_str(), options)?;
+        let mut reader = BufReader::new(File::open(spool_path)?);
+        let size = io::copy(&mut reader, &mut self.zip)?;
+        self.broken = false;
+        Ok(size)
+    }
+
+    // returns the size of the entry and SHA-256 of the added data
+    pub fn add_bytes(&mut self, filename: &str, bytes: &[u8]) -> Result<(u64, String), TransferError> {
+        let size = match &self.key {
+            Some(_) => encrypted_len(bytes.len() as u64),
+            None => bytes.len() as u64
+        };
+        let mut writer = self.open_entry(filename, size >= u32::MAX as u64)?;
+        writer.write_all(bytes)?;
+        writer.finish()
+    }
+
+    pub fn finish(mut self) -> Result<(), TransferError> {
+        let writer = self.zip.finish()?;
+        writer.sync_all()?;
+        std::mem::drop(writer);
+        fs::rename(&self.part_path, &self.dest_path)?;
+        Ok(())
+    }
+
+    // keeps the '.part' file with all complete entries, so it can be reopened later,
+    // returns false if the file was discarded
+    pub fn close_partial(mut self) -> bool {
+        if !self.broken {
+            if let Ok(writer) = self.zip.finish() {
+                if writer.sync_all().is_ok() {
+                    return true;
+                }
+            }
+        }
+        self.discard();
+        false
+    }
+
+    pub fn discard(self) {
+        let part_path = self.part_path.clone();
+        std::mem::drop(self);
+        let _ = fs::remove_file(&part_path);
+    }
+}
@@ -0,0 +1,279 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use regex::Regex;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BcpFieldKind {
+    Fixed(usize),
+    Prefix(usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct BcpFormatColumn {
+    pub name: String,
+    pub sql_type: String,
+    pub kind: BcpFieldKind,
+    pub max_length: i32,
+    pub precision: u8,
+    pub scale: u8,
+    pub nullable: bool,
+    // empty when the collation is not specified in format file
+    pub collation: String,
+    // code page of char data, resolved before the data is decoded
+    pub code_page: u32,
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct BcpFormat {
+    pub columns: Vec<BcpFormatColumn>,
+}
+
+impl BcpFormatColumn {
+    pub fn from_catalog(name: &str, type_name: &str, max_length: i32, precision: i32, scale: i32,
+                        nullable: bool) -> Result<Self, TransferError> {
+        let fixed = |len: usize| {
+            if nullable { BcpFieldKind::Prefix(1) } else { BcpFieldKind::Fixed(len) }
+        };
+        let varlen = if -1 == max_length { BcpFieldKind::Prefix(8) } else { BcpFieldKind::Prefix(2) };
+        let (sql_type, kind, field_max_length) = match type_name.to_lowercase().as_str() {
+            "bit" => ("SQLBIT", fixed(1), 1),
+            "tinyint" => ("SQLTINYINT", fixed(1), 1),
+            "smallint" => ("SQLSMALLINT", fixed(2), 2),
+            "int" => ("SQLINT", fixed(4), 4),
+            "bigint" => ("SQLBIGINT", fixed(8), 8),
+            "real" => ("SQLFLT4", fixed(4), 4),
+            "float" => ("SQLFLT8", fixed(8), 8),
+            "decimal" => ("SQLDECIMAL", fixed(19), 19),
+            "numeric" => ("SQLNUMERIC", fixed(19), 19),
+            "money" => ("SQLMONEY", fixed(8), 8),
+            "smallmoney" => ("SQLMONEY4", fixed(4), 4),
+            "datetime" => ("SQLDATETIME", fixed(8), 8),
+            "smalldatetime" => ("SQLDATETIM4", fixed(4), 4),
+            "date" => ("SQLDATE", BcpFieldKind::Prefix(1), 3),
+            "time" => ("SQLTIME", BcpFieldKind::Prefix(1), 5),
+            "datetime2" => ("SQLDATETIME2", BcpFieldKind::Prefix(1), 8),
+            "datetimeoffset" => ("SQLDATETIMEOFFSET", BcpFieldKind::Prefix(1), 10),
+            "uniqueidentifier" => ("SQLUNIQUEID", fixed(16), 16),
+            "char" => ("SQLCHAR", varlen, max_length),
+            "varchar" => ("SQLVARYCHAR", varlen, max_length),
+            "nchar" => ("SQLNCHAR", varlen, max_length),
+            "nvarchar" => ("SQLNVARCHAR", varlen, max_length),
+            "binary" => ("SQLBINARY", varlen, max_length),
+            "varbinary" => ("SQLVARYBIN", varlen, max_length),
+            "text" => ("SQLTEXT", BcpFieldKind::Prefix(4), -1),
+            "ntext" => ("SQLNTEXT", BcpFieldKind::Prefix(4), -1),
+            "image" => ("SQLIMAGE", BcpFieldKind::Prefix(4), -1),
+            "xml" => ("SQLNVARCHAR", BcpFieldKind::Prefix(8), -1),
+            "timestamp" | "rowversion" => ("SQLBINARY", fixed(8), 8),
+            _ => return Err(TransferError::from_string(format!(
+                "Unsupported column type, column: {}, type: {}", name, type_name)))
+        };
+        Ok(Self {
+            name: name.to_string(),
+            sql_type: sql_type.to_string(),
+            kind,
+            max_length: field_max_length,
+            precision: precision as u8,
+            scale: scale as u8,
+            nullable,
+            collation: String::new(),
+            code_page: 0
+        })
+    }
+
+    pub fn is_unicode(&self) -> bool {
+        "SQLNCHAR" == self.sql_type || "SQLNVARCHAR" == self.sql_type || "SQLNTEXT" == self.sql_type
+    }
+
+    pub fn is_char(&self) -> bool {
+        "SQLCHAR" == self.sql_type || "SQLVARYCHAR" == self.sql_type || "SQLTEXT" == self.sql_type
+    }
+
+    fn has_scale(&self) -> bool {
+        match self.sql_type.as_str() {
+            "SQLTIME" | "SQLDATETIME2" | "SQLDATETIMEOFFSET" => true,
+            _ => false
+        }
+    }
+
+    fn has_precision(&self) -> bool {
+        "SQLDECIMAL" == self.sql_type || "SQLNUMERIC" == self.sql_type
+    }
+}
+
+fn escape_xml(st: &str) -> String {
+    st.replace("&", "&amp;")
+        .replace("<", "&lt;")
+        .replace(">", "&gt;")
+        .replace("\"", "&quot;")
+}
+
+fn unescape_xml(st: &str) -> String {
+    st.replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+fn parse_attributes(re: &Regex, text: &str) -> HashMap<String, String> {
+    re.captures_iter(text)
+        .map(|cap| (cap[1].to_string(), unescape_xml(&cap[2])))
+        .collect()
+}
+
+fn parse_usize(attrs: &HashMap<String, String>, name: &str) -> Result<usize, TransferError> {
+    match attrs.get(name) {
+        Some(st) => Ok(st.parse()?),
+        None => Err(TransferError::from_string(format!(
+            "Format file attribute not found: {}", name)))
+    }
+}
+
+impl BcpFormat {
+    pub fn parse(text: &str) -> Result<Self, TransferError> {
+        let err = |e: regex::Error| TransferError::from_string(format!("Format file parse error: {}", e));
+        let field_re = Regex::new("<FIELD\\s([^>]*)>").map_err(err)?;
+        let column_re = Regex::new("<COLUMN\\s([^>]*)>").map_err(err)?;
+        let attr_re = Regex::new("([\\w:]+)=\"([^\"]*)\"").map_err(err)?;
+
+        let mut fields: HashMap<String, (BcpFieldKind, String)> = HashMap::new();
+        for cap in field_re.captures_iter(text) {
+            let attrs = parse_attributes(&attr_re, &cap[1]);
+            let id = attrs.get("ID").cloned().unwrap_or_default();
+            let kind = match attrs.get("xsi:type").map(|s| s.as_str()) {
+                Some("NativeFixed") => BcpFieldKind::Fixed(parse_usize(&attrs, "LENGTH")?),
+                Some("NativePrefix") => BcpFieldKind::Prefix(parse_usize(&attrs, "PREFIX_LENGTH")?),
+                _ => return Err(TransferError::from_string(format!(
+                    "Unsupported format file field, only native fields are supported, id: {}", id)))
+            };
+            let collation = attrs.get("COLLATION").cloned().unwrap_or_default();
+            fields.insert(id, (kind, collation));
+        }
+
+        let mut columns = Vec::new();
+        for cap in column_re.captures_iter(text) {
+            let attrs = parse_attributes(&attr_re, &cap[1]);
+            let source = attrs.get("SOURCE").cloned().unwrap_or_default();
+            let (kind, collation) = match fields.get(&source) {
+                Some((kind, collation)) => (*kind, collation.clone()),
+                None => return Err(TransferError::from_string(format!(
+                    "Format file field not found, id: {}", source)))
+            };
+            let max_length = match kind {
+                BcpFieldKind::Fixed(len) => len as i32,
+                BcpFieldKind::Prefix(_) => -1
+            };
+            columns.push(BcpFormatColumn {
+                name: attrs.get("NAME").cloned().unwrap_or_default(),
+                sql_type: attrs.get("xsi:type").cloned().unwrap_or_default(),
+                kind,
+                max_length,
+                precision: attrs.get("PRECISION").map(|s| s.parse()).transpose()?.unwrap_or(0),
+                scale: attrs.get("SCALE").map(|s| s.parse()).transpose()?.unwrap_or(0),
+                nullable: "NO" != attrs.get("NULLABLE").map(|s| s.as_str()).unwrap_or("YES"),
+                collation,
+                code_page: 0
+            });
+        }
+        if columns.is_empty() {
+            return Err(TransferError::from_str("No columns found in format file"));
+        }
+
+        Ok(Self {
+            columns
+        })
+    }
+
+    pub fn read_file(path: &Path) -> Result<Self, TransferError> {
+        let bytes = fs::read(path)?;
+        let text = if bytes.len() >= 2 && 0xff == bytes[0] && 0xfe == bytes[1] {
+            let codepoints: Vec<u16> = bytes[2..]
+                .chunks_exact(2)
+                .map(|a| u16::from_le_bytes([a[0], a[1]]))
+                .collect();
+            match String::from_utf16(&codepoints) {
+                Ok(text) => text,
+                Err(e) => return Err(TransferError::from_string(format!(
+                    "Format file read error: {}", e)))
+            }
+        } else {
+            String::from_utf8_lossy(&bytes).to_string()
+        };
+        Self::parse(&text)
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut lines: Vec<String> = vec!(
+            "<?xml version=\"1.0\"?>".to_string(),
+            "<BCPFORMAT xmlns=\"http://schemas.microsoft.com/sqlserver/2004/bulkload/format\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">".to_string(),
+            " <RECORD>".to_string()
+        );
+        for (idx, col) in self.columns.iter().enumerate() {
+            let id = idx + 1;
+            let line = match col.kind {
+                BcpFieldKind::Fixed(len) => format!(
+                    "  <FIELD ID=\"{}\" xsi:type=\"NativeFixed\" LENGTH=\"{}\"/>", id, len),
+                BcpFieldKind::Prefix(prefix_len) => {
+                    let mut ln = format!(
+                        "  <FIELD ID=\"{}\" xsi:type=\"NativePrefix\" PREFIX_LENGTH=\"{}\"", id, prefix_len);
+                    if 2 == prefix_len && col.max_length > 0 {
+                        ln.push_str(&format!(" MAX_LENGTH=\"{}\"", col.max_length));
+                    }
+                    if col.is_char() || col.is_unicode() {
+                        ln.push_str(&format!(" COLLATION=\"{}\"", escape_xml(&col.collation)));
+                    }
+                    ln.push_str("/>");
+                    ln
+                }
+            };
+            lines.push(line);
+        }
+        lines.push(" </RECORD>".to_string());
+        lines.push(" <ROW>".to_string());
+        for (idx, col) in self.columns.iter().enumerate() {
+            let mut line = format!("  <COLUMN SOURCE=\"{}\" NAME=\"{}\" xsi:type=\"{}\"",
+                                   idx + 1, escape_xml(&col.name), &col.sql_type);
+            if col.has_precision() {
+                line.push_str(&format!(" PRECISION=\"{}\" SCALE=\"{}\"", col.precision, col.scale));
+            }
+            if col.has_scale() {
+                line.push_str(&format!(" SCALE=\"{}\"", col.scale));
+            }
+            line.push_str(if col.nullable { " NULLABLE=\"YES\"/>" } else { " NULLABLE=\"NO\"/>" });
+            lines.push(line);
+        }
+        lines.push(" </ROW>".to_string());
+        lines.push("</BCPFORMAT>".to_string());
+        lines.push("".to_string());
+        lines.join("\r\n")
+    }
+
+    pub fn retain_columns(&mut self, indices: &Vec<usize>) {
+        self.columns = indices.iter().map(|idx| self.columns[*idx].clone()).collect();
+    }
+
+    pub fn write_file(&self, path: &Path) -> Result<(), TransferError> {
+        fs::write(path, self.to_xml())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,366 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::borrow::Cow;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+
+use encoding_rs::Encoding;
+use tiberius::ColumnData;
+use tiberius::Uuid;
+use tiberius::numeric::Numeric;
+use tiberius::time::Date;
+use tiberius::time::DateTime;
+use tiberius::time::DateTime2;
+use tiberius::time::DateTimeOffset;
+use tiberius::time::SmallDateTime;
+use tiberius::time::Time;
+
+
+fn time_len(scale: u8) -> usize {
+    match scale {
+        0..=2 => 3,
+        3..=4 => 4,
+        _ => 5
+    }
+}
+
+fn put_time(buf: &mut Vec<u8>, time: Time) {
+    let len = time_len(time.scale());
+    buf.extend_from_slice(&time.increments().to_le_bytes()[0..len]);
+}
+
+fn put_datetime2(buf: &mut Vec<u8>, dt: DateTime2) {
+    put_time(buf, dt.time());
+    buf.extend_from_slice(&dt.date().days().to_le_bytes()[0..3]);
+}
+
+// SQL Server stores first three GUID groups in little-endian order
+pub(super) fn reorder_guid_bytes(bytes: &mut [u8; 16]) {
+    bytes[0..4].reverse();
+    bytes[4..6].reverse();
+    bytes[6..8].reverse();
+}
+
+fn put_money(buf: &mut Vec<u8>, col: &BcpFormatColumn, value: i128, scale: u8) {
+    let scaled = if scale <= 4 {
+        value * 10i128.pow((4 - scale) as u32)
+    } else {
+        value / 10i128.pow((scale - 4) as u32)
+    };
+    if "SQLMONEY4" == col.sql_type {
+        buf.extend_from_slice(&(scaled as i32).to_le_bytes());
+    } else {
+        let val = scaled as i64;
+        buf.extend_from_slice(&((val >> 32) as i32).to_le_bytes());
+        buf.extend_from_slice(&(val as u32).to_le_bytes());
+    }
+}
+
+fn put_decimal(buf: &mut Vec<u8>, col: &BcpFormatColumn, value: i128) {
+    buf.push(col.precision);
+    buf.push(col.scale);
+    buf.push(if value >= 0 { 1 } else { 0 });
+    buf.extend_from_slice(&value.unsigned_abs().to_le_bytes());
+}
+
+// returns false for NULL values
+fn encode_value(buf: &mut Vec<u8>, col: &BcpFormatColumn, data: ColumnData<'static>) -> Result<bool, TransferError> {
+    match data {
+        ColumnData::U8(Some(val)) => buf.push(val),
+        ColumnData::I16(Some(val)) => buf.extend_from_slice(&val.to_le_bytes()),
+        ColumnData::I32(Some(val)) => buf.extend_from_slice(&val.to_le_bytes()),
+        ColumnData::I64(Some(val)) => buf.extend_from_slice(&val.to_le_bytes()),
+        ColumnData::F32(Some(val)) => buf.extend_from_slice(&val.to_le_bytes()),
+        ColumnData::F64(Some(val)) => buf.extend_from_slice(&val.to_le_bytes()),
+        ColumnData::Bit(Some(val)) => buf.push(if val { 1 } else { 0 }),
+        ColumnData::Numeric(Some(num)) => {
+            if "SQLMONEY" == col.sql_type || "SQLMONEY4" == col.sql_type {
+                put_money(buf, col, num.value(), num.scale());
+            } else {
+                put_decimal(buf, col, num.value());
+            }
+        },
+        ColumnData::DateTime(Some(dt)) => {
+            buf.extend_from_slice(&dt.days().to_le_bytes());
+            buf.extend_from_slice(&dt.seconds_fragments().to_le_bytes());
+        },
+        ColumnData::SmallDateTime(Some(dt)) => {
+            buf.extend_from_slice(&dt.days().to_le_bytes());
+            buf.extend_from_slice(&dt.seconds_fragments().to_le_bytes());
+        },
+        ColumnData::Date(Some(date)) => buf.extend_from_slice(&date.days().to_le_bytes()[0..3]),
+        ColumnData::Time(Some(time)) => put_time(buf, time),
+        ColumnData::DateTime2(Some(dt)) => put_datetime2(buf, dt),
+        ColumnData::DateTimeOffset(Some(dto)) => {
+            put_datetime2(buf, dto.datetime2());
+            buf.extend_from_slice(&dto.offset().to_le_bytes());
+        },
+        ColumnData::Guid(Some(uuid)) => {
+            let mut bytes = *uuid.as_bytes();
+            reorder_guid_bytes(&mut bytes);
+            buf.extend_from_slice(&bytes);
+        },
+        ColumnData::String(Some(st)) => {
+            if col.is_unicode() {
+                for cp in st.encode_utf16() {
+                    buf.extend_from_slice(&cp.to_le_bytes());
+                }
+            } else {
+                buf.extend_from_slice(st.as_bytes());
+            }
+        },
+        ColumnData::Binary(Some(bytes)) => buf.extend_from_slice(&bytes),
+        ColumnData::Xml(Some(xml)) => {
+            for cp in xml.to_string().encode_utf16() {
+                buf.extend_from_slice(&cp.to_le_bytes());
+            }
+        },
+        _ => return Ok(false)
+    };
+    Ok(true)
+}
+
+pub fn write_native_value<W: Write>(writer: &mut W, buf: &mut Vec<u8>, col: &BcpFormatColumn,
+                                    data: ColumnData<'static>) -> Result<(), TransferError> {
+    buf.clear();
+    let not_null = encode_value(buf, col, data)?;
+    match col.kind {
+        BcpFieldKind::Fixed(len) => {
+            if !not_null {
+                return Err(TransferError::from_string(format!(
+                    "NULL value in non-nullable column: {}", col.name)));
+            }
+            if len != buf.len() {
+                return Err(TransferError::from_string(format!(
+                    "Invalid value length, column: {}, expected: {}, actual: {}", col.name, len, buf.len())));
+            }
+        },
+        BcpFieldKind::Prefix(prefix_len) => {
+            let len: u64 = if not_null { buf.len() as u64 } else { u64::MAX };
+            writer.write_all(&len.to_le_bytes()[0..prefix_len])?;
+        }
+    };
+    writer.write_all(&buf)?;
+    Ok(())
+}
+
+fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool, TransferError> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => {
+                if 0 == read {
+                    return Ok(false);
+                }
+                return Err(TransferError::from_str("Unexpected end of data file"));
+            },
+            Ok(len) => read += len,
+            Err(e) if ErrorKind::Interrupted == e.kind() => { },
+            Err(e) => return Err(e.into())
+        }
+    }
+    Ok(true)
+}
+
+fn le_uint(bytes: &[u8]) -> u64 {
+    let mut arr = [0u8; 8];
+    let len = bytes.len().min(8);
+    arr[0..len].copy_from_slice(&bytes[0..len]);
+    u64::from_le_bytes(arr)
+}
+
+// returns None at the end of data, Some(false) for NULL values
+pub fn read_native_field<R: Read>(reader: &mut R, buf: &mut Vec<u8>, col: &BcpFormatColumn) -> Result<Option<bool>, TransferError> {
+    let len = match col.kind {
+        BcpFieldKind::Fixed(len) => len,
+        BcpFieldKind::Prefix(prefix_len) => {
+            let mut prefix = [0u8; 8];
+            if !read_exact_or_eof(reader, &mut prefix[0..prefix_len])? {
+                return Ok(None);
+            }
+            let len = le_uint(&prefix[0..prefix_len]);
+            let null_marker = if 8 == prefix_len { u64::MAX } else { (1u64 << (prefix_len * 8)) - 1 };
+            if null_marker == len {
+                buf.clear();
+                return Ok(Some(false));
+            }
+            buf.resize(len as usize, 0);
+            if len > 0 {
+                reader.read_exact(buf)?;
+            }
+            return Ok(Some(true));
+        }
+    };
+    buf.resize(len, 0);
+    if !read_exact_or_eof(reader, buf)? {
+        return Ok(None);
+    }
+    Ok(Some(true))
+}
+
+fn code_page_encoding(code_page: u32) -> Option<&'static Encoding> {
+    match code_page {
+        874 => Some(encoding_rs::WINDOWS_874),
+        932 => Some(encoding_rs::SHIFT_JIS),
+        936 => Some(encoding_rs::GBK),
+        949 => Some(encoding_rs::EUC_KR),
+        950 => Some(encoding_rs::BIG5),
+        1250 => Some(encoding_rs::WINDOWS_1250),
+        1251 => Some(encoding_rs::WINDOWS_1251),
+        1252 => Some(encoding_rs::WINDOWS_1252),
+        1253 => Some(encoding_rs::WINDOWS_1253),
+        1254 => Some(encoding_rs::WINDOWS_1254),
+        1255 => Some(encoding_rs::WINDOWS_1255),
+        1256 => Some(encoding_rs::WINDOWS_1256),
+        1257 => Some(encoding_rs::WINDOWS_1257),
+        1258 => Some(encoding_rs::WINDOWS_1258),
+        65001 => Some(encoding_rs::UTF_8),
+        _ => None
+    }
+}
+
+// char data is decoded using the code page of the column collation
+fn decode_chars(col: &BcpFormatColumn, bytes: &[u8]) -> Result<String, TransferError> {
+    let encoding = match code_page_encoding(col.code_page) {
+        Some(encoding) => encoding,
+        None => return Err(TransferError::from_string(format!(
+            "Unsupported code page of character data, column: {}, code page: {}", col.name, col.code_page)))
+    };
+    let (text, had_errors) = encoding.decode_without_bom_handling(bytes);
+    if had_errors {
+        return Err(TransferError::from_string(format!(
+            "Invalid character data for code page, column: {}, code page: {}", col.name, col.code_page)));
+    }
+    Ok(text.to_string())
+}
+
+fn decode_money(col: &BcpFormatColumn, bytes: &[u8]) -> Result<Numeric, TransferError> {
+    // money is stored as high and low 32-bit halves of the value scaled by 10^4
+    let value = if "SQLMONEY4" == col.sql_type {
+        check_len(col, bytes, 4)?;
+        le_uint(bytes) as u32 as i32 as i64
+    } else {
+        check_len(col, bytes, 8)?;
+        let high = le_uint(&bytes[0..4]) as u32 as i32 as i64;
+        let low = le_uint(&bytes[4..8]) as u32 as i64;
+        (high << 32) | low
+    };
+    Ok(Numeric::new_with_scale(value as i128, 4))
+}
+
+fn decode_time(bytes: &[u8], scale: u8) -> Time {
+    Time::new(le_uint(bytes), scale)
+}
+
+fn decode_datetime2(bytes: &[u8], scale: u8) -> Result<DateTime2, TransferError> {
+    if bytes.len() < 6 {
+        return Err(TransferError::from_str("Invalid datetime2 value length"));
+    }
+    let time_len = bytes.len() - 3;
+    let time = decode_time(&bytes[0..time_len], scale);
+    let date = Date::new(le_uint(&bytes[time_len..]) as u32);
+    Ok(DateTime2::new(date, time))
+}
+
+fn check_len(col: &BcpFormatColumn, bytes: &[u8], expected: usize) -> Result<(), TransferError> {
+    if expected != bytes.len() {
+        return Err(TransferError::from_string(format!(
+            "Invalid value length, column: {}, expected: {}, actual: {}", col.name, expected, bytes.len())));
+    }
+    Ok(())
+}
+
+pub fn decode_native_value(col: &BcpFormatColumn, value: Option<&[u8]>) -> Result<ColumnData<'static>, TransferError> {
+    let data = match col.sql_type.as_str() {
+        "SQLBIT" => ColumnData::Bit(value.map(|b| 0 != le_uint(b))),
+        "SQLTINYINT" => ColumnData::U8(value.map(|b| le_uint(b) as u8)),
+        "SQLSMALLINT" => ColumnData::I16(value.map(|b| le_uint(b) as i16)),
+        "SQLINT" => ColumnData::I32(value.map(|b| le_uint(b) as i32)),
+        "SQLBIGINT" => ColumnData::I64(value.map(|b| le_uint(b) as i64)),
+        "SQLFLT4" => ColumnData::F32(value.map(|b| f32::from_bits(le_uint(b) as u32))),
+        "SQLFLT8" => ColumnData::F64(value.map(|b| f64::from_bits(le_uint(b)))),
+        "SQLDECIMAL" | "SQLNUMERIC" => match value {
+            Some(b) => {
+                check_len(col, b, 19)?;
+                let mut arr = [0u8; 16];
+                arr.copy_from_slice(&b[3..19]);
+                let abs = i128::from_le_bytes(arr);
+                let val = if 0 == b[2] { -abs } else { abs };
+                ColumnData::Numeric(Some(Numeric::new_with_scale(val, b[1])))
+            },
+            None => ColumnData::Numeric(None)
+        },
+        "SQLMONEY" | "SQLMONEY4" => ColumnData::Numeric(value.map(|b| decode_money(col, b)).transpose()?),
+        "SQLDATETIME" => match value {
+            Some(b) => {
+                check_len(col, b, 8)?;
+                ColumnData::DateTime(Some(DateTime::new(le_uint(&b[0..4]) as i32, le_uint(&b[4..8]) as u32)))
+            },
+            None => ColumnData::DateTime(None)
+        },
+        "SQLDATETIM4" => match value {
+            Some(b) => {
+                check_len(col, b, 4)?;
+                ColumnData::SmallDateTime(Some(SmallDateTime::new(le_uint(&b[0..2]) as u16, le_uint(&b[2..4]) as u16)))
+            },
+            None => ColumnData::SmallDateTime(None)
+        },
+        "SQLDATE" => ColumnData::Date(value.map(|b| Date::new(le_uint(b) as u32))),
+        "SQLTIME" => ColumnData::Time(value.map(|b| decode_time(b, col.scale))),
+        "SQLDATETIME2" => ColumnData::DateTime2(value.map(|b| decode_datetime2(b, col.scale)).transpose()?),
+        "SQLDATETIMEOFFSET" => match value {
+            Some(b) => {
+                if b.len() < 8 {
+                    return Err(TransferError::from_str("Invalid datetimeoffset value length"));
+                }
+                let dt_len = b.len() - 2;
+                let dt = decode_datetime2(&b[0..dt_len], col.scale)?;
+                let offset = le_uint(&b[dt_len..]) as i16;
+                ColumnData::DateTimeOffset(Some(DateTimeOffset::new(dt, offset)))
+            },
+            None => ColumnData::DateTimeOffset(None)
+        },
+        "SQLUNIQUEID" => match value {
+            Some(b) => {
+                check_len(col, b, 16)?;
+                let mut arr = [0u8; 16];
+                arr.copy_from_slice(b);
+                reorder_guid_bytes(&mut arr);
+                ColumnData::Guid(Some(Uuid::from_bytes(arr)))
+            },
+            None => ColumnData::Guid(None)
+        },
+        "SQLNCHAR" | "SQLNVARCHAR" | "SQLNTEXT" => match value {
+            Some(b) => {
+                let codepoints: Vec<u16> = b
+                    .chunks_exact(2)
+                    .map(|a| u16::from_le_bytes([a[0], a[1]]))
+                    .collect();
+                ColumnData::String(Some(Cow::Owned(String::from_utf16_lossy(&codepoints))))
+            },
+            None => ColumnData::String(None)
+        },
+        "SQLCHAR" | "SQLVARYCHAR" | "SQLTEXT" => ColumnData::String(value.map(|b| decode_chars(col, b)).transpose()?.map(Cow::Owned)),
+        "SQLBINARY" | "SQLVARYBIN" | "SQLIMAGE" => ColumnData::Binary(value.map(|b| Cow::Owned(b.to_vec()))),
+        _ => return Err(TransferError::from_string(format!(
+            "Column type is not supported by TDS bulk load, column: {}, type: {}", col.name, col.sql_type)))
+    };
+    Ok(data)
+}
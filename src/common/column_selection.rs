@@ -0,0 +1,52 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+#[derive(Default, Clone)]
+pub struct ColumnSelection {
+    pub schema: String,
+    pub table: String,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl ColumnSelection {
+    pub fn matches(&self, schema: &str, table: &str) -> bool {
+        self.schema.eq_ignore_ascii_case(schema) && self.table.eq_ignore_ascii_case(table)
+    }
+
+    // returns indices of the selected columns in table order
+    pub fn select(&self, format: &BcpFormat) -> Result<Vec<usize>, TransferError> {
+        let contains = |list: &Vec<String>, name: &str| list.iter().any(|n| n.eq_ignore_ascii_case(name));
+        for name in self.include.iter().chain(self.exclude.iter()) {
+            if !format.columns.iter().any(|c| c.name.eq_ignore_ascii_case(name)) {
+                return Err(TransferError::from_string(format!(
+                    "Selected column not found, table: {}.{}, column: {}", &self.schema, &self.table, name)));
+            }
+        }
+        let indices: Vec<usize> = format.columns.iter().enumerate()
+            .filter(|(_, c)| self.include.is_empty() || contains(&self.include, &c.name))
+            .filter(|(_, c)| !contains(&self.exclude, &c.name))
+            .map(|(idx, _)| idx)
+            .collect();
+        if indices.is_empty() {
+            return Err(TransferError::from_string(format!(
+                "No columns selected for export, table: {}.{}", &self.schema, &self.table)));
+        }
+        Ok(indices)
+    }
+}
@@ -0,0 +1,123 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::io;
+use std::io::Write;
+
+use flate2::write::GzEncoder;
+use sha2::Digest;
+use sha2::Sha256;
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum CompressionCodec {
+    #[default]
+    Zstd,
+    Gzip,
+    Uncompressed,
+}
+
+impl CompressionCodec {
+    // appended to the '.bcp' data file name
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            CompressionCodec::Zstd => ".zstd",
+            CompressionCodec::Gzip => ".gz",
+            CompressionCodec::Uncompressed => "",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CompressionCodec::Zstd => "zstd",
+            CompressionCodec::Gzip => "gzip",
+            CompressionCodec::Uncompressed => "none",
+        }
+    }
+
+    pub fn default_level(&self) -> i32 {
+        match self {
+            CompressionCodec::Zstd => 1,
+            CompressionCodec::Gzip => 6,
+            CompressionCodec::Uncompressed => 0,
+        }
+    }
+}
+
+enum DataEncoder<W: Write> {
+    Zstd(zstd::stream::Encoder<'static, W>),
+    Gzip(GzEncoder<W>),
+    Uncompressed(W),
+}
+
+// hashes the data before compression, so the checksum does not depend on codec,
+// compressed data is written into an export file entry or into a data directory file
+pub struct DataWriter<W: Write> {
+    encoder: DataEncoder<W>,
+    hasher: Sha256,
+}
+
+impl<W: Write> DataWriter<W> {
+    pub fn create(writer: W, codec: CompressionCodec, level: i32, threads: u32) -> Result<Self, TransferError> {
+        let encoder = match codec {
+            CompressionCodec::Zstd => {
+                let mut encoder = zstd::stream::Encoder::new(writer, level)?;
+                if threads > 0 {
+                    let _ = encoder.multithread(threads);
+                }
+                DataEncoder::Zstd(encoder)
+            },
+            CompressionCodec::Gzip => DataEncoder::Gzip(GzEncoder::new(writer, flate2::Compression::new(level as u32))),
+            CompressionCodec::Uncompressed => DataEncoder::Uncompressed(writer),
+        };
+        Ok(Self {
+            encoder,
+            hasher: Sha256::new()
+        })
+    }
+
+    // returns the underlying writer and SHA-256 of the uncompressed data
+    pub fn finish(self) -> Result<(W, String), TransferError> {
+        let mut writer = match self.encoder {
+            DataEncoder::Zstd(encoder) => encoder.finish()?,
+            DataEncoder::Gzip(encoder) => encoder.finish()?,
+            DataEncoder::Uncompressed(writer) => writer,
+        };
+        writer.flush()?;
+        Ok((writer, format!("{:x}", self.hasher.finalize())))
+    }
+}
+
+impl<W: Write> Write for DataWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = match &mut self.encoder {
+            DataEncoder::Zstd(encoder) => encoder.write(buf)?,
+            DataEncoder::Gzip(encoder) => encoder.write(buf)?,
+            DataEncoder::Uncompressed(writer) => writer.write(buf)?,
+        };
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.encoder {
+            DataEncoder::Zstd(encoder) => encoder.flush(),
+            DataEncoder::Gzip(encoder) => encoder.flush(),
+            DataEncoder::Uncompressed(writer) => writer.flush(),
+        }
+    }
+}
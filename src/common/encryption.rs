@@ -0,0 +1,386 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+
+use aes_gcm::Aes256Gcm;
+use aes_gcm::Key;
+use aes_gcm::Nonce;
+use aes_gcm::aead::Aead;
+use aes_gcm::aead::KeyInit;
+use aes_gcm::aead::OsRng;
+use aes_gcm::aead::Payload;
+use aes_gcm::aead::rand_core::RngCore;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+// stored unencrypted, all other entries are encrypted when this entry is present
+pub static ENCRYPTION_FILENAME: &str = "encryption.json";
+
+static ALGORITHM: &str = "aes-256-gcm";
+static KDF: &str = "pbkdf2-hmac-sha256";
+static KDF_ITERATIONS: u32 = 600_000;
+static MAGIC: &[u8; 8] = b"WDBENC01";
+static CHUNK_SIZE: usize = 1 << 20;
+static TAG_SIZE: usize = 16;
+static LAST_CHUNK_FLAG: u32 = 1 << 31;
+
+// passphrase or contents of a key file, encryption is not used when empty
+#[derive(Default, Clone)]
+pub struct EncryptionSecret {
+    pub secret: Vec<u8>,
+}
+
+impl EncryptionSecret {
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        Self {
+            secret: passphrase.as_bytes().to_vec()
+        }
+    }
+
+    pub fn from_key_file(path: &str) -> Result<Self, TransferError> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(TransferError::from_string(format!(
+                "Error reading key file, path: {}, message: {}", path, e)))
+        };
+        // trailing line ending is not a part of the key
+        let len = bytes.iter().rposition(|b| !b.is_ascii_whitespace()).map(|pos| pos + 1).unwrap_or(0);
+        if 0 == len {
+            return Err(TransferError::from_string(format!(
+                "Key file is empty, path: {}", path)));
+        }
+        Ok(Self {
+            secret: bytes[..len].to_vec()
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.secret.is_empty()
+    }
+}
+
+#[derive(Clone)]
+pub struct EncryptionKey {
+    cipher: Aes256Gcm,
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct EncryptionInfo {
+    pub algorithm: String,
+    pub kdf: String,
+    pub iterations: u32,
+    pub salt: String,
+    pub key_check: String,
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(st: &str) -> Result<Vec<u8>, TransferError> {
+    if 0 != st.len() % 2 || !st.is_ascii() {
+        return Err(TransferError::from_string(format!("Invalid hex string: {}", st)));
+    }
+    (0..st.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&st[i..i + 2], 16)
+            .map_err(|_| TransferError::from_string(format!("Invalid hex string: {}", st))))
+        .collect()
+}
+
+fn derive_key_bytes(secret: &EncryptionSecret, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(&secret.secret, salt, iterations, &mut key);
+    key
+}
+
+// allows to report a wrong passphrase before decrypting any entry
+fn key_check(key: &[u8; 32]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"wdb_transfer key check");
+    hasher.update(key);
+    format!("{:x}", hasher.finalize())
+}
+
+impl EncryptionInfo {
+    pub fn generate(secret: &EncryptionSecret) -> Result<(Self, EncryptionKey), TransferError> {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key_bytes(secret, &salt, KDF_ITERATIONS);
+        let info = Self {
+            algorithm: ALGORITHM.to_string(),
+            kdf: KDF.to_string(),
+            iterations: KDF_ITERATIONS,
+            salt: to_hex(&salt),
+            key_check: key_check(&key)
+        };
+        Ok((info, EncryptionKey::new(&key)))
+    }
+
+    pub fn derive_key(&self, secret: &EncryptionSecret) -> Result<EncryptionKey, TransferError> {
+        if ALGORITHM != self.algorithm || KDF != self.kdf {
+            return Err(TransferError::from_string(format!(
+                "Unsupported encryption, algorithm: {}, key derivation: {}", &self.algorithm, &self.kdf)));
+        }
+        let key = derive_key_bytes(secret, &from_hex(&self.salt)?, self.iterations);
+        if key_check(&key) != self.key_check {
+            return Err(TransferError::from_str(
+                "Invalid passphrase or key file, export file cannot be decrypted"));
+        }
+        Ok(EncryptionKey::new(&key))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, TransferError> {
+        match serde_json::from_str(text) {
+            Ok(info) => Ok(info),
+            Err(e) => Err(TransferError::from_string(format!(
+                "Encryption info parse error: {}", e)))
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, TransferError> {
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => Ok(json),
+            Err(e) => Err(TransferError::from_string(format!(
+                "Encryption info write error: {}", e)))
+        }
+    }
+}
+
+impl EncryptionKey {
+    fn new(key: &[u8; 32]) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+        }
+    }
+}
+
+// nonce is unique for each chunk, last chunk is marked to detect truncated entries
+fn chunk_nonce(prefix: &[u8; 7], counter: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..7].copy_from_slice(prefix);
+    nonce[7..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = if last { 1 } else { 0 };
+    nonce
+}
+
+// binds each chunk to its entry and position, so chunks cannot be moved between entries
+fn chunk_aad(name: &str, counter: u32, last: bool) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(name.len() + 5);
+    aad.extend_from_slice(name.as_bytes());
+    aad.extend_from_slice(&counter.to_be_bytes());
+    aad.push(if last { 1 } else { 0 });
+    aad
+}
+
+// encrypted entry: magic, nonce prefix, then chunks of up to 1 MiB,
+// each chunk is prefixed with its length, the high bit of the length marks the last chunk
+pub fn encrypted_len(plain_len: u64) -> u64 {
+    let chunk_size = CHUNK_SIZE as u64;
+    let chunks = std::cmp::max(1, (plain_len + chunk_size - 1) / chunk_size);
+    (MAGIC.len() + 7) as u64 + chunks * (4 + TAG_SIZE as u64) + plain_len
+}
+
+pub struct EncryptingWriter<W: Write> {
+    writer: W,
+    key: EncryptionKey,
+    name: String,
+    prefix: [u8; 7],
+    counter: u32,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    pub fn new(mut writer: W, key: &EncryptionKey, name: &str) -> io::Result<Self> {
+        let mut prefix = [0u8; 7];
+        OsRng.fill_bytes(&mut prefix);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&prefix)?;
+        Ok(Self {
+            writer,
+            key: key.clone(),
+            name: name.to_string(),
+            prefix,
+            counter: 0,
+            buf: Vec::with_capacity(CHUNK_SIZE)
+        })
+    }
+
+    fn write_chunk(&mut self, last: bool) -> io::Result<()> {
+        let nonce = chunk_nonce(&self.prefix, self.counter, last);
+        let aad = chunk_aad(&self.name, self.counter, last);
+        let payload = Payload { msg: self.buf.as_slice(), aad: aad.as_slice() };
+        let encrypted = self.key.cipher.encrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Encryption error"))?;
+        let mut header = encrypted.len() as u32;
+        if last {
+            header |= LAST_CHUNK_FLAG;
+        }
+        self.writer.write_all(&header.to_le_bytes())?;
+        self.writer.write_all(&encrypted)?;
+        self.counter = self.counter.checked_add(1)
+            .ok_or(io::Error::new(io::ErrorKind::Other, "Encrypted entry is too large"))?;
+        self.buf.clear();
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_chunk(true)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // full chunk is written only when more data follows, so the last chunk is never empty
+        if CHUNK_SIZE == self.buf.len() {
+            self.write_chunk(false)?;
+        }
+        let len = std::cmp::min(buf.len(), CHUNK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+pub struct DecryptingReader<R: Read> {
+    reader: R,
+    key: EncryptionKey,
+    name: String,
+    prefix: Option<[u8; 7]>,
+    counter: u32,
+    buf: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl<R: Read> DecryptingReader<R> {
+    pub fn new(reader: R, key: &EncryptionKey, name: &str) -> Self {
+        Self {
+            reader,
+            key: key.clone(),
+            name: name.to_string(),
+            prefix: None,
+            counter: 0,
+            buf: Vec::new(),
+            pos: 0,
+            done: false
+        }
+    }
+
+    fn read_prefix(&mut self) -> io::Result<[u8; 7]> {
+        let mut header = [0u8; 15];
+        self.reader.read_exact(&mut header).map_err(|_| invalid_data("Entry is not encrypted"))?;
+        if MAGIC[..] != header[..8] {
+            return Err(invalid_data("Entry is not encrypted"));
+        }
+        let mut prefix = [0u8; 7];
+        prefix.copy_from_slice(&header[8..]);
+        Ok(prefix)
+    }
+
+    fn read_chunk(&mut self) -> io::Result<()> {
+        let prefix = match self.prefix {
+            Some(prefix) => prefix,
+            None => {
+                let prefix = self.read_prefix()?;
+                self.prefix = Some(prefix);
+                prefix
+            }
+        };
+        let mut header = [0u8; 4];
+        self.reader.read_exact(&mut header).map_err(|_| invalid_data("Encrypted entry is truncated"))?;
+        let header = u32::from_le_bytes(header);
+        let last = 0 != (header & LAST_CHUNK_FLAG);
+        let len = (header & !LAST_CHUNK_FLAG) as usize;
+        if len < TAG_SIZE || len > CHUNK_SIZE + TAG_SIZE {
+            return Err(invalid_data("Encrypted entry is corrupted"));
+        }
+        let mut encrypted = vec![0u8; len];
+        self.reader.read_exact(&mut encrypted).map_err(|_| invalid_data("Encrypted entry is truncated"))?;
+        let nonce = chunk_nonce(&prefix, self.counter, last);
+        let aad = chunk_aad(&self.name, self.counter, last);
+        let payload = Payload { msg: encrypted.as_slice(), aad: aad.as_slice() };
+        self.buf = self.key.cipher.decrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| invalid_data("Decryption failed, invalid key or corrupted entry"))?;
+        self.pos = 0;
+        self.counter = self.counter.wrapping_add(1);
+        self.done = last;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.read_chunk()?;
+        }
+        let len = std::cmp::min(buf.len(), self.buf.len() - self.pos);
+        buf[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+// entries are read as is when the key is not specified,
+// name is the full name of the ZIP entry the data was encrypted for
+pub fn decrypting_reader<'a, R: Read + 'a>(reader: R, key: Option<&EncryptionKey>, name: &str) -> Box<dyn Read + 'a> {
+    match key {
+        Some(key) => Box::new(DecryptingReader::new(reader, key, name)),
+        None => Box::new(reader)
+    }
+}
+
+// returns the key for an encrypted archive, secret is ignored for unencrypted archives
+pub fn archive_key<R: Read + Seek>(zip: &mut ZipArchive<R>, dirname: &str, secret: &EncryptionSecret) -> Result<Option<EncryptionKey>, TransferError> {
+    let mut entry = match zip.by_name(&format!("{}/{}", dirname, ENCRYPTION_FILENAME)) {
+        Ok(entry) => entry,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into())
+    };
+    let mut text = String::new();
+    entry.read_to_string(&mut text)?;
+    if !secret.is_enabled() {
+        return Err(TransferError::from_str(
+            "Export file is encrypted, passphrase or key file must be specified"));
+    }
+    let info = EncryptionInfo::parse(&text)?;
+    Ok(Some(info.derive_key(secret)?))
+}
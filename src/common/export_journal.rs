@@ -0,0 +1,126 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Default, Clone)]
+pub struct JournalFile {
+    pub filename: String,
+    pub size: u64,
+    pub sha256: String,
+    pub row_count: i64,
+}
+
+// journal format, one line per exported table or table part:
+// schema TAB table TAB part (TAB filename TAB size TAB sha256 TAB row_count)*,
+// sha256 of data files is computed over uncompressed data, row count is the number
+// of rows written into a data file, -1 for other files or when it is not known
+pub struct ExportJournal {
+    path: PathBuf,
+    file: File,
+    tables: HashMap<(String, String, String), Vec<JournalFile>>,
+}
+
+impl ExportJournal {
+    pub fn create(path: &Path, dbname: &str) -> Result<Self, TransferError> {
+        let mut file = File::create(path)?;
+        file.write_all(format!("export\t{}\r\n", dbname).as_bytes())?;
+        file.sync_data()?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            tables: HashMap::new()
+        })
+    }
+
+    pub fn open(path: &Path, dbname: &str) -> Result<Self, TransferError> {
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines();
+        let header = lines.next().unwrap_or("");
+        if header != format!("export\t{}", dbname) {
+            return Err(TransferError::from_string(format!(
+                "Export journal does not match database: {}, path: {}", dbname, path.to_string_lossy())));
+        }
+        let mut tables = HashMap::new();
+        for ln in lines {
+            let parts: Vec<&str> = ln.split('\t').collect();
+            // skip a line that was not written completely
+            if parts.len() < 3 || 0 != (parts.len() - 3) % 4 {
+                continue;
+            }
+            let mut files = Vec::new();
+            for chunk in parts[3..].chunks_exact(4) {
+                files.push(JournalFile {
+                    filename: chunk[0].to_string(),
+                    size: chunk[1].parse()?,
+                    sha256: chunk[2].to_string(),
+                    row_count: chunk[3].parse()?
+                });
+            }
+            tables.insert((parts[0].to_string(), parts[1].to_string(), parts[2].to_string()), files);
+        }
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            tables
+        })
+    }
+
+    pub fn table_files(&self, schema: &str, table: &str, part: &str) -> Option<&Vec<JournalFile>> {
+        self.tables.get(&(schema.to_string(), table.to_string(), part.to_string()))
+    }
+
+    // records of the table and of all its parts
+    pub fn table_records(&self, schema: &str, table: &str) -> Vec<(&str, &Vec<JournalFile>)> {
+        let mut records: Vec<(&str, &Vec<JournalFile>)> = self.tables.iter()
+            .filter(|((s, t, _), _)| s == schema && t == table)
+            .map(|((_, _, part), files)| (part.as_str(), files))
+            .collect();
+        records.sort_by_key(|(part, _)| *part);
+        records
+    }
+
+    pub fn all_files(&self) -> impl Iterator<Item = &JournalFile> {
+        self.tables.values().flat_map(|files| files.iter())
+    }
+
+    pub fn record(&mut self, schema: &str, table: &str, part: &str, files: Vec<JournalFile>) -> Result<(), TransferError> {
+        let mut ln = format!("{}\t{}\t{}", schema, table, part);
+        for jf in files.iter() {
+            ln.push_str(&format!("\t{}\t{}\t{}\t{}", &jf.filename, jf.size, &jf.sha256, jf.row_count));
+        }
+        ln.push_str("\r\n");
+        self.file.write_all(ln.as_bytes())?;
+        self.file.sync_data()?;
+        self.tables.insert((schema.to_string(), table.to_string(), part.to_string()), files);
+        Ok(())
+    }
+
+    pub fn remove(self) {
+        let path = self.path.clone();
+        std::mem::drop(self);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,132 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::io::Write;
+
+use futures_util::TryStreamExt;
+use tiberius::Client;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_util::compat::Compat;
+
+pub(super) struct NativeTableFormat {
+    pub(super) format: BcpFormat,
+    pub(super) select_list: Vec<String>,
+}
+
+impl NativeTableFormat {
+    pub(super) fn retain_columns(&mut self, indices: &Vec<usize>) {
+        self.format.retain_columns(indices);
+        self.select_list = indices.iter().map(|idx| self.select_list[*idx].clone()).collect();
+    }
+}
+
+fn select_expression(name: &str, type_name: &str) -> String {
+    let col = quote_ident(name);
+    // char data is exported as raw bytes to keep the column code page intact,
+    // money is read as decimal to not lose precision in floating point
+    match type_name.to_lowercase().as_str() {
+        "char" | "varchar" | "text" => format!("cast(cast({} as varchar(max)) as varbinary(max))", col),
+        "ntext" | "xml" => format!("cast({} as nvarchar(max))", col),
+        "image" => format!("cast({} as varbinary(max))", col),
+        "money" => format!("cast({} as decimal(19, 4))", col),
+        "smallmoney" => format!("cast({} as decimal(10, 4))", col),
+        _ => col
+    }
+}
+
+pub(super) fn load_native_format(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                                 schema: &str, table: &str) -> Result<NativeTableFormat, TransferError> {
+    runtime.block_on(async {
+        let mut query = tiberius::Query::new("\
+                select
+                    c.name,
+                    type_name(c.system_type_id) as type_name,
+                    cast(c.max_length as int) as max_length,
+                    cast(c.precision as int) as precision,
+                    cast(c.scale as int) as scale,
+                    c.is_nullable
+                from sys.columns as c
+                where c.object_id = object_id(@P1)
+                order by c.column_id");
+        query.bind(quote_table(schema, table));
+        let rows = query.query(client).await?.into_first_result().await?;
+        let msg = "Columns select error";
+        let mut format = BcpFormat::default();
+        let mut select_list = Vec::new();
+        for row in rows.iter() {
+            let name: &str = row.get(0).ok_or(TransferError::from_str(msg))?;
+            let type_name: &str = row.get(1).ok_or(TransferError::from_str(msg))?;
+            let max_length: i32 = row.get(2).ok_or(TransferError::from_str(msg))?;
+            let precision: i32 = row.get(3).ok_or(TransferError::from_str(msg))?;
+            let scale: i32 = row.get(4).ok_or(TransferError::from_str(msg))?;
+            let nullable: bool = row.get(5).ok_or(TransferError::from_str(msg))?;
+            format.columns.push(BcpFormatColumn::from_catalog(
+                name, type_name, max_length, precision, scale, nullable)?);
+            select_list.push(select_expression(name, type_name));
+        }
+        if format.columns.is_empty() {
+            return Err(TransferError::from_string(format!(
+                "Table columns not found, table: {}.{}", schema, table)));
+        }
+        Ok(NativeTableFormat {
+            format,
+            select_list
+        })
+    })
+}
+
+pub(super) fn select_native_format(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, schema: &str, table: &str,
+                                   selection: Option<&ColumnSelection>) -> Result<NativeTableFormat, TransferError> {
+    let mut ntf = load_native_format(runtime, client, schema, table)?;
+    if let Some(sel) = selection {
+        let indices = sel.select(&ntf.format)?;
+        ntf.retain_columns(&indices);
+    }
+    Ok(ntf)
+}
+
+// format file contents are taken from the returned format with 'to_xml'
+pub(super) fn run_native_format<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                                                 schema: &str, table: &str, selection: Option<&ColumnSelection>) -> Result<NativeTableFormat, TransferError> {
+    progress_fun(&format!("Creating format file: {}.{}", schema, table));
+    select_native_format(runtime, client, schema, table, selection)
+}
+
+pub(super) fn export_native_rows<P: Fn(&str)->(), W: Write>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                                                            schema: &str, table: &str, predicate: &str, ntf: &NativeTableFormat, writer: &mut W) -> Result<u64, TransferError> {
+    let mut sql = format!("select {} from {}", ntf.select_list.join(", "), quote_table(schema, table));
+    if !predicate.is_empty() {
+        sql.push_str(&format!(" where {}", predicate));
+    }
+    runtime.block_on(async {
+        let mut stream = tiberius::Query::new(sql).query(client).await?.into_row_stream();
+        let mut buf: Vec<u8> = Vec::new();
+        let mut count: u64 = 0;
+        while let Some(row) = stream.try_next().await? {
+            for (col, data) in ntf.format.columns.iter().zip(row.into_iter()) {
+                write_native_value(writer, &mut buf, col, data)?;
+            }
+            count += 1;
+            if 0 == count % 100000 {
+                progress_fun(&format!("{} rows exported", count));
+            }
+        }
+        Ok(count)
+    })
+}
@@ -0,0 +1,377 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::io::Write;
+use std::sync::Arc;
+
+use futures_util::TryStreamExt;
+use parquet::basic::Compression;
+use parquet::basic::GzipLevel;
+use parquet::basic::LogicalType;
+use parquet::basic::Repetition;
+use parquet::basic::TimeUnit;
+use parquet::basic::Type as PhysicalType;
+use parquet::basic::ZstdLevel;
+use parquet::data_type::BoolType;
+use parquet::data_type::ByteArray;
+use parquet::data_type::ByteArrayType;
+use parquet::data_type::DoubleType;
+use parquet::data_type::FixedLenByteArray;
+use parquet::data_type::FixedLenByteArrayType;
+use parquet::data_type::FloatType;
+use parquet::data_type::Int32Type;
+use parquet::data_type::Int64Type;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use tiberius::Client;
+use tiberius::ColumnData;
+use tiberius::numeric::Numeric;
+use tiberius::time::DateTime2;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_util::compat::Compat;
+
+// days from 0001-01-01 and from 1900-01-01 to 1970-01-01
+static EPOCH_DAYS_0001: i64 = 719162;
+static EPOCH_DAYS_1900: i64 = 25567;
+static MICROS_PER_DAY: i64 = 86_400_000_000;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ParquetFormat {
+    // rows are buffered in memory until the row group is written
+    pub row_group_rows: usize,
+}
+
+impl Default for ParquetFormat {
+    fn default() -> Self {
+        Self {
+            row_group_rows: 100_000
+        }
+    }
+}
+
+// decimals are stored as integers with the column scale,
+// date and time values are stored with microsecond precision
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ParquetKind {
+    Bool,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Float,
+    Double,
+    Decimal32,
+    Decimal64,
+    Decimal128,
+    Date,
+    Timestamp,
+    TimestampUtc,
+    Time,
+    Uuid,
+    String,
+    Binary,
+}
+
+enum ParquetValues {
+    Bool(Vec<bool>),
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+    Bytes(Vec<ByteArray>),
+    Fixed(Vec<FixedLenByteArray>),
+}
+
+struct ParquetColumn {
+    name: String,
+    kind: ParquetKind,
+    scale: u8,
+    nullable: bool,
+    values: ParquetValues,
+    def_levels: Vec<i16>,
+}
+
+fn parquet_kind(col: &BcpFormatColumn) -> Result<(ParquetKind, u8, u8), TransferError> {
+    let kind = match col.sql_type.as_str() {
+        "SQLBIT" => ParquetKind::Bool,
+        "SQLTINYINT" => ParquetKind::Int8,
+        "SQLSMALLINT" => ParquetKind::Int16,
+        "SQLINT" => ParquetKind::Int32,
+        "SQLBIGINT" => ParquetKind::Int64,
+        "SQLFLT4" => ParquetKind::Float,
+        "SQLFLT8" => ParquetKind::Double,
+        // money is read as decimal, INT64 decimals are limited to 18 digits
+        "SQLMONEY" => return Ok((ParquetKind::Decimal128, 19, 4)),
+        "SQLMONEY4" => return Ok((ParquetKind::Decimal64, 10, 4)),
+        "SQLDECIMAL" | "SQLNUMERIC" => {
+            if col.precision <= 9 {
+                ParquetKind::Decimal32
+            } else if col.precision <= 18 {
+                ParquetKind::Decimal64
+            } else {
+                ParquetKind::Decimal128
+            }
+        },
+        "SQLDATE" => ParquetKind::Date,
+        "SQLDATETIME" | "SQLDATETIM4" | "SQLDATETIME2" => ParquetKind::Timestamp,
+        "SQLDATETIMEOFFSET" => ParquetKind::TimestampUtc,
+        "SQLTIME" => ParquetKind::Time,
+        "SQLUNIQUEID" => ParquetKind::Uuid,
+        "SQLCHAR" | "SQLVARYCHAR" | "SQLTEXT" | "SQLNCHAR" | "SQLNVARCHAR" | "SQLNTEXT" => ParquetKind::String,
+        "SQLBINARY" | "SQLVARYBIN" | "SQLIMAGE" => ParquetKind::Binary,
+        _ => return Err(TransferError::from_string(format!(
+            "Unsupported column type for Parquet, column: {}, type: {}", col.name, col.sql_type)))
+    };
+    Ok((kind, col.precision, col.scale))
+}
+
+fn micros_unit() -> TimeUnit {
+    TimeUnit::MICROS(Default::default())
+}
+
+fn schema_field(col: &ParquetColumn, precision: u8) -> Result<Arc<Type>, TransferError> {
+    let (physical, logical) = match col.kind {
+        ParquetKind::Bool => (PhysicalType::BOOLEAN, None),
+        ParquetKind::Int8 => (PhysicalType::INT32, Some(LogicalType::Integer { bit_width: 8, is_signed: false })),
+        ParquetKind::Int16 => (PhysicalType::INT32, Some(LogicalType::Integer { bit_width: 16, is_signed: true })),
+        ParquetKind::Int32 => (PhysicalType::INT32, None),
+        ParquetKind::Int64 => (PhysicalType::INT64, None),
+        ParquetKind::Float => (PhysicalType::FLOAT, None),
+        ParquetKind::Double => (PhysicalType::DOUBLE, None),
+        ParquetKind::Decimal32 => (PhysicalType::INT32, None),
+        ParquetKind::Decimal64 => (PhysicalType::INT64, None),
+        ParquetKind::Decimal128 => (PhysicalType::FIXED_LEN_BYTE_ARRAY, None),
+        ParquetKind::Date => (PhysicalType::INT32, Some(LogicalType::Date)),
+        ParquetKind::Timestamp => (PhysicalType::INT64, Some(LogicalType::Timestamp { is_adjusted_to_u_t_c: false, unit: micros_unit() })),
+        ParquetKind::TimestampUtc => (PhysicalType::INT64, Some(LogicalType::Timestamp { is_adjusted_to_u_t_c: true, unit: micros_unit() })),
+        ParquetKind::Time => (PhysicalType::INT64, Some(LogicalType::Time { is_adjusted_to_u_t_c: false, unit: micros_unit() })),
+        ParquetKind::Uuid => (PhysicalType::FIXED_LEN_BYTE_ARRAY, Some(LogicalType::Uuid)),
+        ParquetKind::String => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+        ParquetKind::Binary => (PhysicalType::BYTE_ARRAY, None),
+    };
+    let repetition = if col.nullable { Repetition::OPTIONAL } else { Repetition::REQUIRED };
+    let mut builder = Type::primitive_type_builder(&col.name, physical)
+        .with_repetition(repetition)
+        .with_logical_type(logical);
+    match col.kind {
+        ParquetKind::Decimal32 | ParquetKind::Decimal64 | ParquetKind::Decimal128 => {
+            builder = builder
+                .with_logical_type(Some(LogicalType::Decimal { scale: col.scale as i32, precision: precision as i32 }))
+                .with_precision(precision as i32)
+                .with_scale(col.scale as i32);
+            if ParquetKind::Decimal128 == col.kind {
+                builder = builder.with_length(16);
+            }
+        },
+        ParquetKind::Uuid => builder = builder.with_length(16),
+        _ => {}
+    };
+    match builder.build() {
+        Ok(tp) => Ok(Arc::new(tp)),
+        Err(e) => Err(TransferError::from_string(format!(
+            "Parquet schema error, column: {}, message: {}", &col.name, e)))
+    }
+}
+
+fn increments_to_micros(increments: u64, scale: u8) -> i64 {
+    if scale <= 6 {
+        (increments * 10u64.pow((6 - scale) as u32)) as i64
+    } else {
+        (increments / 10u64.pow((scale - 6) as u32)) as i64
+    }
+}
+
+fn datetime2_micros(dt: DateTime2) -> i64 {
+    (dt.date().days() as i64 - EPOCH_DAYS_0001) * MICROS_PER_DAY + increments_to_micros(dt.time().increments(), dt.time().scale())
+}
+
+fn rescale_numeric(num: Numeric, scale: u8) -> i128 {
+    if num.scale() <= scale {
+        num.value() * 10i128.pow((scale - num.scale()) as u32)
+    } else {
+        num.value() / 10i128.pow((num.scale() - scale) as u32)
+    }
+}
+
+impl ParquetColumn {
+    fn new(col: &BcpFormatColumn) -> Result<(Self, u8), TransferError> {
+        let (kind, precision, scale) = parquet_kind(col)?;
+        let values = match kind {
+            ParquetKind::Bool => ParquetValues::Bool(Vec::new()),
+            ParquetKind::Int8 | ParquetKind::Int16 | ParquetKind::Int32 | ParquetKind::Decimal32 |
+            ParquetKind::Date => ParquetValues::Int32(Vec::new()),
+            ParquetKind::Int64 | ParquetKind::Decimal64 | ParquetKind::Timestamp | ParquetKind::TimestampUtc |
+            ParquetKind::Time => ParquetValues::Int64(Vec::new()),
+            ParquetKind::Float => ParquetValues::Float(Vec::new()),
+            ParquetKind::Double => ParquetValues::Double(Vec::new()),
+            ParquetKind::Decimal128 | ParquetKind::Uuid => ParquetValues::Fixed(Vec::new()),
+            ParquetKind::String | ParquetKind::Binary => ParquetValues::Bytes(Vec::new()),
+        };
+        Ok((Self {
+            name: col.name.clone(),
+            kind,
+            scale,
+            nullable: col.nullable,
+            values,
+            def_levels: Vec::new()
+        }, precision))
+    }
+
+    fn push(&mut self, data: ColumnData<'static>) -> Result<(), TransferError> {
+        let scale = self.scale;
+        let present = match (&mut self.values, data) {
+            (ParquetValues::Bool(vals), ColumnData::Bit(val)) => val.map(|v| vals.push(v)).is_some(),
+            (ParquetValues::Int32(vals), ColumnData::U8(val)) => val.map(|v| vals.push(v as i32)).is_some(),
+            (ParquetValues::Int32(vals), ColumnData::I16(val)) => val.map(|v| vals.push(v as i32)).is_some(),
+            (ParquetValues::Int32(vals), ColumnData::I32(val)) => val.map(|v| vals.push(v)).is_some(),
+            (ParquetValues::Int32(vals), ColumnData::Numeric(val)) => val.map(|v| vals.push(rescale_numeric(v, scale) as i32)).is_some(),
+            (ParquetValues::Int32(vals), ColumnData::Date(val)) => val.map(|v| vals.push((v.days() as i64 - EPOCH_DAYS_0001) as i32)).is_some(),
+            (ParquetValues::Int64(vals), ColumnData::I64(val)) => val.map(|v| vals.push(v)).is_some(),
+            (ParquetValues::Int64(vals), ColumnData::Numeric(val)) => val.map(|v| vals.push(rescale_numeric(v, scale) as i64)).is_some(),
+            (ParquetValues::Int64(vals), ColumnData::DateTime(val)) => val.map(|v| {
+                // fragments are 1/300 of a second
+                let micros = (v.seconds_fragments() as i64 * 10000 + 1) / 3;
+                vals.push((v.days() as i64 - EPOCH_DAYS_1900) * MICROS_PER_DAY + micros)
+            }).is_some(),
+            (ParquetValues::Int64(vals), ColumnData::SmallDateTime(val)) => val.map(|v| {
+                let micros = v.seconds_fragments() as i64 * 60_000_000;
+                vals.push((v.days() as i64 - EPOCH_DAYS_1900) * MICROS_PER_DAY + micros)
+            }).is_some(),
+            (ParquetValues::Int64(vals), ColumnData::DateTime2(val)) => val.map(|v| vals.push(datetime2_micros(v))).is_some(),
+            // datetimeoffset value is in UTC
+            (ParquetValues::Int64(vals), ColumnData::DateTimeOffset(val)) => val.map(|v| vals.push(datetime2_micros(v.datetime2()))).is_some(),
+            (ParquetValues::Int64(vals), ColumnData::Time(val)) => val.map(|v| vals.push(increments_to_micros(v.increments(), v.scale()))).is_some(),
+            (ParquetValues::Float(vals), ColumnData::F32(val)) => val.map(|v| vals.push(v)).is_some(),
+            (ParquetValues::Double(vals), ColumnData::F64(val)) => val.map(|v| vals.push(v)).is_some(),
+            (ParquetValues::Fixed(vals), ColumnData::Numeric(val)) => val.map(|v| {
+                vals.push(FixedLenByteArray::from(rescale_numeric(v, scale).to_be_bytes().to_vec()))
+            }).is_some(),
+            (ParquetValues::Fixed(vals), ColumnData::Guid(val)) => val.map(|v| vals.push(FixedLenByteArray::from(v.as_bytes().to_vec()))).is_some(),
+            (ParquetValues::Bytes(vals), ColumnData::String(val)) => val.map(|v| vals.push(ByteArray::from(v.as_bytes().to_vec()))).is_some(),
+            (ParquetValues::Bytes(vals), ColumnData::Xml(val)) => val.map(|v| vals.push(ByteArray::from(v.to_string().into_bytes()))).is_some(),
+            (ParquetValues::Bytes(vals), ColumnData::Binary(val)) => val.map(|v| vals.push(ByteArray::from(v.to_vec()))).is_some(),
+            _ => return Err(TransferError::from_string(format!(
+                "Unexpected value type for Parquet column: {}, kind: {:?}", &self.name, self.kind)))
+        };
+        if !present && !self.nullable {
+            return Err(TransferError::from_string(format!(
+                "NULL value in non-nullable column: {}", &self.name)));
+        }
+        self.def_levels.push(if present { 1 } else { 0 });
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        match &mut self.values {
+            ParquetValues::Bool(vals) => vals.clear(),
+            ParquetValues::Int32(vals) => vals.clear(),
+            ParquetValues::Int64(vals) => vals.clear(),
+            ParquetValues::Float(vals) => vals.clear(),
+            ParquetValues::Double(vals) => vals.clear(),
+            ParquetValues::Bytes(vals) => vals.clear(),
+            ParquetValues::Fixed(vals) => vals.clear(),
+        };
+        self.def_levels.clear();
+    }
+}
+
+fn parquet_compression(codec: CompressionCodec, level: i32) -> Result<Compression, TransferError> {
+    Ok(match codec {
+        CompressionCodec::Zstd => Compression::ZSTD(ZstdLevel::try_new(level)?),
+        CompressionCodec::Gzip => Compression::GZIP(GzipLevel::try_new(level as u32)?),
+        CompressionCodec::Uncompressed => Compression::UNCOMPRESSED,
+    })
+}
+
+fn write_row_group<W: Write + Send>(writer: &mut SerializedFileWriter<W>, columns: &mut Vec<ParquetColumn>) -> Result<(), TransferError> {
+    let mut row_group = writer.next_row_group()?;
+    for pc in columns.iter_mut() {
+        let mut col = match row_group.next_column()? {
+            Some(col) => col,
+            None => return Err(TransferError::from_string(format!(
+                "Parquet column writer not found, column: {}", &pc.name)))
+        };
+        let def_levels = if pc.nullable { Some(pc.def_levels.as_slice()) } else { None };
+        match &pc.values {
+            ParquetValues::Bool(vals) => col.typed::<BoolType>().write_batch(vals, def_levels, None)?,
+            ParquetValues::Int32(vals) => col.typed::<Int32Type>().write_batch(vals, def_levels, None)?,
+            ParquetValues::Int64(vals) => col.typed::<Int64Type>().write_batch(vals, def_levels, None)?,
+            ParquetValues::Float(vals) => col.typed::<FloatType>().write_batch(vals, def_levels, None)?,
+            ParquetValues::Double(vals) => col.typed::<DoubleType>().write_batch(vals, def_levels, None)?,
+            ParquetValues::Bytes(vals) => col.typed::<ByteArrayType>().write_batch(vals, def_levels, None)?,
+            ParquetValues::Fixed(vals) => col.typed::<FixedLenByteArrayType>().write_batch(vals, def_levels, None)?,
+        };
+        col.close()?;
+        pc.clear();
+    }
+    row_group.close()?;
+    Ok(())
+}
+
+// pages are compressed by the Parquet writer, so the file is not compressed again
+pub(super) fn export_parquet_rows<P: Fn(&str)->(), W: Write + Send>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, schema: &str, table: &str,
+                                                                    predicate: &str, ntf: &export_native::NativeTableFormat, pf: &ParquetFormat, codec: CompressionCodec,
+                                                                    level: i32, writer: W) -> Result<u64, TransferError> {
+    let mut columns = Vec::new();
+    let mut fields = Vec::new();
+    for col in ntf.format.columns.iter() {
+        let (pc, precision) = ParquetColumn::new(col)?;
+        fields.push(schema_field(&pc, precision)?);
+        columns.push(pc);
+    }
+    let message = match Type::group_type_builder("schema").with_fields(fields).build() {
+        Ok(tp) => tp,
+        Err(e) => return Err(TransferError::from_string(format!(
+            "Parquet schema error, table: {}.{}, message: {}", schema, table, e)))
+    };
+    let props = WriterProperties::builder()
+        .set_compression(parquet_compression(codec, level)?)
+        .set_max_row_group_size(pf.row_group_rows)
+        .build();
+    let mut file_writer = SerializedFileWriter::new(writer, Arc::new(message), Arc::new(props))?;
+
+    let mut sql = format!("select {} from {}", ntf.select_list.join(", "), quote_table(schema, table));
+    if !predicate.is_empty() {
+        sql.push_str(&format!(" where {}", predicate));
+    }
+    let count = runtime.block_on(async {
+        let mut stream = tiberius::Query::new(sql).query(client).await?.into_row_stream();
+        let mut count: u64 = 0;
+        let mut buffered: usize = 0;
+        while let Some(row) = stream.try_next().await? {
+            for (pc, data) in columns.iter_mut().zip(row.into_iter()) {
+                pc.push(data)?;
+            }
+            buffered += 1;
+            count += 1;
+            if buffered >= pf.row_group_rows {
+                write_row_group(&mut file_writer, &mut columns)?;
+                buffered = 0;
+                progress_fun(&format!("{} rows exported", count));
+            }
+        }
+        if buffered > 0 {
+            write_row_group(&mut file_writer, &mut columns)?;
+        }
+        Ok::<u64, TransferError>(count)
+    })?;
+    file_writer.close()?;
+    Ok(count)
+}
@@ -0,0 +1,292 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::io::Write;
+
+use futures_util::TryStreamExt;
+use tiberius::Client;
+use tiberius::ColumnData;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_util::compat::Compat;
+
+pub static LOAD_SCRIPT_FILENAME: &str = "load_data.sql";
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct SqlFormat {
+    // SQL Server accepts up to 1000 rows in a single 'values' clause
+    pub batch_rows: usize,
+}
+
+impl Default for SqlFormat {
+    fn default() -> Self {
+        Self {
+            batch_rows: 100
+        }
+    }
+}
+
+pub(super) struct SqlTableFormat {
+    pub(super) ntf: export_native::NativeTableFormat,
+    pub(super) identity: bool,
+    pub(super) self_references: Vec<String>,
+}
+
+// line breaks and sqlcmd variable references are kept out of the script text,
+// such values are concatenated as nvarchar(max) to not be truncated to 4000 characters
+fn unicode_literal(st: &str) -> String {
+    if !st.contains(|c: char| '\r' == c || '\n' == c || '\0' == c) && !st.contains("$(") {
+        return format!("N'{}'", st.replace("'", "''"));
+    }
+    let mut lit = "cast(N'' as nvarchar(max)) + N'".to_string();
+    for ch in st.chars() {
+        match ch {
+            '\'' => lit.push_str("''"),
+            '\r' | '\n' | '\0' => lit.push_str(&format!("' + nchar({}) + N'", ch as u32)),
+            '(' if lit.ends_with('$') => lit.push_str("' + N'("),
+            _ => lit.push(ch)
+        }
+    }
+    lit.push('\'');
+    lit
+}
+
+// datetime values use ISO 8601 form with 'T' separator that does not depend on session language
+fn sql_literal(data: ColumnData<'static>) -> Result<String, TransferError> {
+    // floats are written in exponent form to keep all digits in range of float literal
+    let (unicode, quoted, iso) = match &data {
+        ColumnData::F32(Some(val)) => return Ok(format!("{:e}", val)),
+        ColumnData::F64(Some(val)) => return Ok(format!("{:e}", val)),
+        ColumnData::String(_) | ColumnData::Xml(_) => (true, false, false),
+        ColumnData::DateTime(_) | ColumnData::SmallDateTime(_) | ColumnData::DateTime2(_) => (false, true, true),
+        ColumnData::Date(_) | ColumnData::Time(_) | ColumnData::DateTimeOffset(_) | ColumnData::Guid(_) => (false, true, false),
+        _ => (false, false, false)
+    };
+    let st = match export_text::format_value(data)? {
+        Some(st) => st,
+        None => return Ok("NULL".to_string())
+    };
+    Ok(if unicode {
+        unicode_literal(&st)
+    } else if iso {
+        quote_literal(&st.replacen(' ', "T", 1))
+    } else if quoted {
+        quote_literal(&st)
+    } else {
+        st
+    })
+}
+
+async fn load_column_flags(client: &mut Client<Compat<TcpStream>>, schema: &str,
+                           table: &str) -> Result<Vec<(String, bool, bool)>, TransferError> {
+    let mut query = tiberius::Query::new("\
+            select
+                c.name,
+                c.is_identity,
+                cast(case when c.is_computed = 1 or type_name(c.system_type_id) = 'timestamp'
+                    then 1 else 0 end as bit) as is_generated
+            from sys.columns as c
+            where c.object_id = object_id(@P1)");
+    query.bind(quote_table(schema, table));
+    let rows = query.query(client).await?.into_first_result().await?;
+    let msg = "Columns select error";
+    let mut res = Vec::new();
+    for row in rows.iter() {
+        let name: &str = row.get(0).ok_or(TransferError::from_str(msg))?;
+        let is_identity: bool = row.get(1).ok_or(TransferError::from_str(msg))?;
+        let is_generated: bool = row.get(2).ok_or(TransferError::from_str(msg))?;
+        res.push((name.to_string(), is_identity, is_generated));
+    }
+    Ok(res)
+}
+
+// foreign keys that reference the same table are disabled during the load,
+// so the rows can be inserted in any order
+async fn load_self_references(client: &mut Client<Compat<TcpStream>>, schema: &str,
+                              table: &str) -> Result<Vec<String>, TransferError> {
+    let mut query = tiberius::Query::new("\
+            select fk.name
+            from sys.foreign_keys as fk
+            where fk.parent_object_id = object_id(@P1)
+            and fk.referenced_object_id = fk.parent_object_id
+            and fk.is_disabled = 0
+            order by fk.name");
+    query.bind(quote_table(schema, table));
+    let rows = query.query(client).await?.into_first_result().await?;
+    let msg = "Foreign keys select error";
+    let mut res = Vec::new();
+    for row in rows.iter() {
+        let name: &str = row.get(0).ok_or(TransferError::from_str(msg))?;
+        res.push(name.to_string());
+    }
+    Ok(res)
+}
+
+// computed and rowversion columns cannot be inserted and are skipped
+pub(super) fn select_sql_format(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, schema: &str, table: &str,
+                                selection: Option<&ColumnSelection>) -> Result<SqlTableFormat, TransferError> {
+    let mut ntf = export_text::select_text_format(runtime, client, schema, table, selection)?;
+    let flags = runtime.block_on(load_column_flags(client, schema, table))?;
+    let generated = |name: &str| flags.iter().any(|(nm, _, gen)| nm == name && *gen);
+    let indices: Vec<usize> = ntf.format.columns.iter().enumerate()
+        .filter(|(_, col)| !generated(&col.name))
+        .map(|(idx, _)| idx)
+        .collect();
+    if indices.is_empty() {
+        return Err(TransferError::from_string(format!(
+            "Table has no columns that can be inserted, table: {}.{}", schema, table)));
+    }
+    ntf.retain_columns(&indices);
+    let identity = ntf.format.columns.iter()
+        .any(|col| flags.iter().any(|(nm, ident, _)| *nm == col.name && *ident));
+    let self_references = runtime.block_on(load_self_references(client, schema, table))?;
+    Ok(SqlTableFormat {
+        ntf,
+        identity,
+        self_references
+    })
+}
+
+// script is written in UTF-8 with BOM, so it is recognized by sqlcmd,
+// statements are separated with 'GO' to keep the batches small,
+// session settings are restored when a batch fails, self-referencing foreign keys
+// are enabled without checking existing rows, load script checks them at the end
+pub(super) fn export_sql_rows<P: Fn(&str)->(), W: Write>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, schema: &str, table: &str,
+                                                         predicate: &str, stf: &SqlTableFormat, sf: &SqlFormat, writer: &mut W) -> Result<u64, TransferError> {
+    let ntf = &stf.ntf;
+    let mut sql = format!("select {} from {}", ntf.select_list.join(", "), quote_table(schema, table));
+    if !predicate.is_empty() {
+        sql.push_str(&format!(" where {}", predicate));
+    }
+    let qtable = quote_table(schema, table);
+    let column_list: Vec<String> = ntf.format.columns.iter().map(|col| quote_ident(&col.name)).collect();
+    let insert = format!("insert into {} ({}) values\r\n", &qtable, column_list.join(", "));
+    let batch_rows = sf.batch_rows.max(1) as u64;
+
+    let mut setup = String::new();
+    let mut restore = String::new();
+    if stf.identity {
+        setup.push_str(&format!("set identity_insert {} on;\r\n", &qtable));
+        restore.push_str(&format!("set identity_insert {} off;\r\n", &qtable));
+    }
+    for fk in stf.self_references.iter() {
+        setup.push_str(&format!("alter table {} nocheck constraint {};\r\n", &qtable, quote_ident(fk)));
+        restore.push_str(&format!("alter table {} check constraint {};\r\n", &qtable, quote_ident(fk)));
+    }
+    let (batch_start, batch_end) = if restore.is_empty() {
+        (insert, ";\r\nGO\r\n".to_string())
+    } else {
+        (format!("begin try\r\n{}", &insert),
+         format!(";\r\nend try\r\nbegin catch\r\n{}throw;\r\nend catch\r\nGO\r\n", &restore))
+    };
+
+    writer.write_all(&[0xef, 0xbb, 0xbf])?;
+    let text = format!("-- data: {}\r\nset nocount on;\r\n{}GO\r\n", &qtable, &setup);
+    writer.write_all(text.as_bytes())?;
+    let count = runtime.block_on(async {
+        let mut stream = tiberius::Query::new(sql).query(client).await?.into_row_stream();
+        let mut count: u64 = 0;
+        let mut line = String::new();
+        while let Some(row) = stream.try_next().await? {
+            line.clear();
+            if 0 == count % batch_rows {
+                line.push_str(&batch_start);
+            } else {
+                line.push_str(",\r\n");
+            }
+            line.push('(');
+            for (idx, data) in row.into_iter().enumerate() {
+                if idx > 0 {
+                    line.push_str(", ");
+                }
+                line.push_str(&sql_literal(data)?);
+            }
+            line.push(')');
+            count += 1;
+            if 0 == count % batch_rows {
+                line.push_str(&batch_end);
+            }
+            writer.write_all(line.as_bytes())?;
+            if 0 == count % 100000 {
+                progress_fun(&format!("{} rows exported", count));
+            }
+        }
+        if 0 != count % batch_rows {
+            writer.write_all(batch_end.as_bytes())?;
+        }
+        Ok::<u64, TransferError>(count)
+    })?;
+    if !restore.is_empty() {
+        writer.write_all(format!("{}GO\r\n", &restore).as_bytes())?;
+    }
+    Ok(count)
+}
+
+// tables in circular references are added in the list order
+fn dependency_order(parents: &Vec<Vec<usize>>) -> Vec<usize> {
+    let mut done: Vec<bool> = parents.iter().map(|_| false).collect();
+    let mut order = Vec::new();
+    while order.len() < parents.len() {
+        let ready: Vec<usize> = (0..parents.len())
+            .filter(|idx| !done[*idx] && parents[*idx].iter().all(|p| done[*p]))
+            .collect();
+        if ready.is_empty() {
+            order.extend((0..parents.len()).filter(|idx| !done[*idx]));
+            break;
+        }
+        for idx in ready {
+            done[idx] = true;
+            order.push(idx);
+        }
+    }
+    order
+}
+
+// includes table scripts with sqlcmd ':r' command, parent tables go before
+// the tables that reference them, so foreign keys are satisfied on insert,
+// self-referencing foreign keys are checked after all the table files are loaded
+pub(super) fn write_load_script<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, dbname: &str, tables: &Vec<(String, String)>,
+                                                 data_files: &Vec<Vec<String>>) -> Result<String, TransferError> {
+    progress_fun("Writing data load script ...");
+    let parents = match load_table_dependencies(cc, dbname) {
+        Ok(deps) => parent_indices(tables, &deps),
+        Err(e) => {
+            progress_fun(&format!("Foreign keys are not loaded, tables are listed in any order, error: {}", e));
+            tables.iter().map(|_| Vec::new()).collect()
+        }
+    };
+    let runtime = cc.create_runtime()?;
+    let mut client = cc.open_connection_to_db(&runtime, dbname)?;
+    let mut text = format!("-- run from the directory with the data files: sqlcmd -d <database> -i {}\r\n",
+                           LOAD_SCRIPT_FILENAME);
+    for idx in dependency_order(&parents) {
+        if data_files[idx].is_empty() {
+            continue;
+        }
+        for filename in data_files[idx].iter() {
+            text.push_str(&format!(":r \"{}\"\r\n", filename));
+        }
+        let (schema, table) = &tables[idx];
+        let self_references = runtime.block_on(load_self_references(&mut client, schema, table))?;
+        for fk in self_references.iter() {
+            text.push_str(&format!("alter table {} with check check constraint {};\r\nGO\r\n",
+                                   quote_table(schema, table), quote_ident(fk)));
+        }
+    }
+    Ok(text)
+}
@@ -0,0 +1,257 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::io::Write;
+
+use chrono::NaiveDate;
+use futures_util::TryStreamExt;
+use tiberius::Client;
+use tiberius::ColumnData;
+use tiberius::time::DateTime2;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_util::compat::Compat;
+
+// day numbers of 0001-01-01 and 1900-01-01 counted from 0001-01-01 as day 1
+static DAYS_CE_0001: i64 = 1;
+static DAYS_CE_1900: i64 = 693596;
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum TextQuoting {
+    // only values with delimiters, quotes, line breaks or equal to NULL text are quoted
+    #[default]
+    Minimal,
+    All,
+    Never,
+}
+
+impl TextQuoting {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TextQuoting::Minimal => "minimal",
+            TextQuoting::All => "all",
+            TextQuoting::Never => "none",
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum TextEncoding {
+    #[default]
+    Utf8,
+    Utf8Bom,
+    Utf16,
+}
+
+impl TextEncoding {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TextEncoding::Utf8 => "utf8",
+            TextEncoding::Utf8Bom => "utf8bom",
+            TextEncoding::Utf16 => "utf16",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TextFormat {
+    pub delimiter: char,
+    pub quoting: TextQuoting,
+    pub header: bool,
+    pub encoding: TextEncoding,
+    pub null_value: String,
+}
+
+impl Default for TextFormat {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            quoting: TextQuoting::Minimal,
+            header: true,
+            encoding: TextEncoding::Utf8,
+            null_value: String::new()
+        }
+    }
+}
+
+fn format_date(days_from_ce: i64) -> Result<String, TransferError> {
+    match i32::try_from(days_from_ce).ok().and_then(NaiveDate::from_num_days_from_ce_opt) {
+        Some(date) => Ok(date.format("%Y-%m-%d").to_string()),
+        None => Err(TransferError::from_string(format!(
+            "Invalid date value, days: {}", days_from_ce)))
+    }
+}
+
+fn format_time(increments: u64, scale: u8) -> String {
+    let pow = 10u64.pow(scale as u32);
+    let secs = increments / pow;
+    let mut st = format!("{:02}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60);
+    if scale > 0 {
+        st.push_str(&format!(".{:0width$}", increments % pow, width = scale as usize));
+    }
+    st
+}
+
+// offset is applied to UTC value to get the local time
+fn format_datetime2(dt: DateTime2, offset_minutes: i16) -> Result<String, TransferError> {
+    let scale = dt.time().scale();
+    let pow = 10i128.pow(scale as u32);
+    let day_increments = 86400 * pow;
+    let total = dt.date().days() as i128 * day_increments + dt.time().increments() as i128
+        + offset_minutes as i128 * 60 * pow;
+    let days = total.div_euclid(day_increments) as i64;
+    let increments = total.rem_euclid(day_increments) as u64;
+    Ok(format!("{} {}", format_date(DAYS_CE_0001 + days)?, format_time(increments, scale)))
+}
+
+fn to_hex_upper(bytes: &[u8]) -> String {
+    let mut st = String::with_capacity(2 + bytes.len() * 2);
+    st.push_str("0x");
+    for b in bytes {
+        st.push_str(&format!("{:02X}", b));
+    }
+    st
+}
+
+// returns None for NULL values
+pub(super) fn format_value(data: ColumnData<'static>) -> Result<Option<String>, TransferError> {
+    let st = match data {
+        ColumnData::U8(Some(val)) => val.to_string(),
+        ColumnData::I16(Some(val)) => val.to_string(),
+        ColumnData::I32(Some(val)) => val.to_string(),
+        ColumnData::I64(Some(val)) => val.to_string(),
+        ColumnData::F32(Some(val)) => val.to_string(),
+        ColumnData::F64(Some(val)) => val.to_string(),
+        ColumnData::Bit(Some(val)) => if val { "1".to_string() } else { "0".to_string() },
+        ColumnData::Numeric(Some(num)) => num.to_string(),
+        ColumnData::DateTime(Some(dt)) => {
+            // fragments are 1/300 of a second
+            let millis = (dt.seconds_fragments() as u64 * 1000 + 150) / 300;
+            format!("{} {}", format_date(DAYS_CE_1900 + dt.days() as i64)?, format_time(millis, 3))
+        },
+        ColumnData::SmallDateTime(Some(dt)) => {
+            let secs = dt.seconds_fragments() as u64 * 60;
+            format!("{} {}", format_date(DAYS_CE_1900 + dt.days() as i64)?, format_time(secs, 0))
+        },
+        ColumnData::Date(Some(date)) => format_date(DAYS_CE_0001 + date.days() as i64)?,
+        ColumnData::Time(Some(time)) => format_time(time.increments(), time.scale()),
+        ColumnData::DateTime2(Some(dt)) => format_datetime2(dt, 0)?,
+        ColumnData::DateTimeOffset(Some(dto)) => {
+            let offset = dto.offset();
+            let sign = if offset < 0 { '-' } else { '+' };
+            let abs = offset.unsigned_abs();
+            format!("{} {}{:02}:{:02}", format_datetime2(dto.datetime2(), offset)?, sign, abs / 60, abs % 60)
+        },
+        ColumnData::Guid(Some(uuid)) => uuid.to_string().to_uppercase(),
+        ColumnData::String(Some(st)) => st.to_string(),
+        ColumnData::Binary(Some(bytes)) => to_hex_upper(&bytes),
+        ColumnData::Xml(Some(xml)) => xml.to_string(),
+        _ => return Ok(None)
+    };
+    Ok(Some(st))
+}
+
+fn push_field(line: &mut String, tf: &TextFormat, value: &str) {
+    let quote = match tf.quoting {
+        TextQuoting::All => true,
+        TextQuoting::Never => false,
+        TextQuoting::Minimal => value == tf.null_value || value.contains(tf.delimiter) ||
+            value.contains(|c: char| '"' == c || '\r' == c || '\n' == c)
+    };
+    if quote {
+        line.push('"');
+        line.push_str(&value.replace("\"", "\"\""));
+        line.push('"');
+    } else {
+        line.push_str(value);
+    }
+}
+
+fn write_text<W: Write>(writer: &mut W, buf: &mut Vec<u8>, encoding: TextEncoding, text: &str) -> Result<(), TransferError> {
+    match encoding {
+        TextEncoding::Utf16 => {
+            buf.clear();
+            for cp in text.encode_utf16() {
+                buf.extend_from_slice(&cp.to_le_bytes());
+            }
+            writer.write_all(buf)?;
+        },
+        _ => writer.write_all(text.as_bytes())?
+    };
+    Ok(())
+}
+
+// char data is read as unicode text instead of raw bytes
+pub(super) fn select_text_format(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, schema: &str, table: &str,
+                                 selection: Option<&ColumnSelection>) -> Result<export_native::NativeTableFormat, TransferError> {
+    let mut ntf = export_native::select_native_format(runtime, client, schema, table, selection)?;
+    for (col, expr) in ntf.format.columns.iter().zip(ntf.select_list.iter_mut()) {
+        if col.is_char() {
+            *expr = format!("cast({} as nvarchar(max))", quote_ident(&col.name));
+        }
+    }
+    Ok(ntf)
+}
+
+pub(super) fn export_text_rows<P: Fn(&str)->(), W: Write>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, schema: &str, table: &str,
+                                                          predicate: &str, ntf: &export_native::NativeTableFormat, tf: &TextFormat, writer: &mut W) -> Result<u64, TransferError> {
+    let mut sql = format!("select {} from {}", ntf.select_list.join(", "), quote_table(schema, table));
+    if !predicate.is_empty() {
+        sql.push_str(&format!(" where {}", predicate));
+    }
+    let mut buf: Vec<u8> = Vec::new();
+    match tf.encoding {
+        TextEncoding::Utf8Bom => writer.write_all(&[0xef, 0xbb, 0xbf])?,
+        TextEncoding::Utf16 => writer.write_all(&[0xff, 0xfe])?,
+        TextEncoding::Utf8 => {}
+    };
+    let mut line = String::new();
+    if tf.header {
+        for (idx, col) in ntf.format.columns.iter().enumerate() {
+            if idx > 0 {
+                line.push(tf.delimiter);
+            }
+            push_field(&mut line, tf, &col.name);
+        }
+        line.push_str("\r\n");
+        write_text(writer, &mut buf, tf.encoding, &line)?;
+    }
+    runtime.block_on(async {
+        let mut stream = tiberius::Query::new(sql).query(client).await?.into_row_stream();
+        let mut count: u64 = 0;
+        while let Some(row) = stream.try_next().await? {
+            line.clear();
+            for (idx, data) in row.into_iter().enumerate() {
+                if idx > 0 {
+                    line.push(tf.delimiter);
+                }
+                match format_value(data)? {
+                    Some(value) => push_field(&mut line, tf, &value),
+                    None => line.push_str(&tf.null_value)
+                };
+            }
+            line.push_str("\r\n");
+            write_text(writer, &mut buf, tf.encoding, &line)?;
+            count += 1;
+            if 0 == count % 100000 {
+                progress_fun(&format!("{} rows exported", count));
+            }
+        }
+        Ok(count)
+    })
+}
@@ -0,0 +1,147 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use sha2::Digest;
+use sha2::Sha256;
+use zip::ZipArchive;
+
+// identifies the archive by its central directory, without reading the data
+pub fn archive_identity<R: Read + Seek>(zip: &mut ZipArchive<R>, file_len: u64) -> Result<String, TransferError> {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}\n", file_len).as_bytes());
+    for i in 0..zip.len() {
+        let entry = zip.by_index(i)?;
+        hasher.update(format!("{}\t{}\t{}\n", entry.name(), entry.size(), entry.crc32()).as_bytes());
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// journal format, header line followed by one line per table state change:
+// prepared|started|done TAB schema TAB table TAB part,
+// 'prepared' is recorded with empty part for the tables created or truncated by the import
+pub struct ImportJournal {
+    path: PathBuf,
+    file: File,
+    prepared: HashSet<(String, String, String)>,
+    started: HashSet<(String, String, String)>,
+    done: HashSet<(String, String, String)>,
+}
+
+impl ImportJournal {
+    fn header(identity: &str, dbname: &str) -> String {
+        format!("import\t{}\t{}", identity, dbname)
+    }
+
+    pub fn create(path: &Path, identity: &str, dbname: &str) -> Result<Self, TransferError> {
+        let mut file = File::create(path)?;
+        file.write_all(format!("{}\r\n", Self::header(identity, dbname)).as_bytes())?;
+        file.sync_data()?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            prepared: HashSet::new(),
+            started: HashSet::new(),
+            done: HashSet::new()
+        })
+    }
+
+    pub fn open(path: &Path, identity: &str, dbname: &str) -> Result<Self, TransferError> {
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines();
+        if lines.next().unwrap_or("") != Self::header(identity, dbname) {
+            return Err(TransferError::from_string(format!(
+                "Import journal was created for a different file or database, path: {}", path.to_string_lossy())));
+        }
+        let mut prepared = HashSet::new();
+        let mut started = HashSet::new();
+        let mut done = HashSet::new();
+        for ln in lines {
+            let parts: Vec<&str> = ln.split('\t').collect();
+            if 4 != parts.len() {
+                continue;
+            }
+            let key = (parts[1].to_string(), parts[2].to_string(), parts[3].to_string());
+            match parts[0] {
+                "prepared" => { prepared.insert(key); },
+                "started" => { started.insert(key); },
+                "done" => { done.insert(key); },
+                _ => { }
+            }
+        }
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            prepared,
+            started,
+            done
+        })
+    }
+
+    pub fn is_prepared(&self, schema: &str, table: &str) -> bool {
+        self.prepared.contains(&(schema.to_string(), table.to_string(), String::new()))
+    }
+
+    pub fn is_started(&self, schema: &str, table: &str, part: &str) -> bool {
+        self.started.contains(&(schema.to_string(), table.to_string(), part.to_string()))
+    }
+
+    pub fn is_done(&self, schema: &str, table: &str, part: &str) -> bool {
+        self.done.contains(&(schema.to_string(), table.to_string(), part.to_string()))
+    }
+
+    fn append(&mut self, state: &str, schema: &str, table: &str, part: &str) -> Result<(), TransferError> {
+        self.file.write_all(format!("{}\t{}\t{}\t{}\r\n", state, schema, table, part).as_bytes())?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    pub fn mark_prepared(&mut self, schema: &str, table: &str) -> Result<(), TransferError> {
+        self.append("prepared", schema, table, "")?;
+        self.prepared.insert((schema.to_string(), table.to_string(), String::new()));
+        Ok(())
+    }
+
+    pub fn mark_started(&mut self, schema: &str, table: &str, part: &str) -> Result<(), TransferError> {
+        self.append("started", schema, table, part)?;
+        self.started.insert((schema.to_string(), table.to_string(), part.to_string()));
+        Ok(())
+    }
+
+    pub fn mark_done(&mut self, schema: &str, table: &str, part: &str) -> Result<(), TransferError> {
+        self.append("done", schema, table, part)?;
+        self.done.insert((schema.to_string(), table.to_string(), part.to_string()));
+        Ok(())
+    }
+
+    pub fn remove(self) {
+        let path = self.path.clone();
+        std::mem::drop(self);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,130 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Read;
+use std::path::Path;
+
+use tiberius::Client;
+use tiberius::SqlBulkCopyOptions;
+use tiberius::TokenRow;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_util::compat::Compat;
+
+pub(super) fn load_column_names(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                                schema: &str, table: &str) -> Result<Vec<String>, TransferError> {
+    runtime.block_on(async {
+        let mut query = tiberius::Query::new("\
+                select c.name
+                from sys.columns as c
+                where c.object_id = object_id(@P1)
+                order by c.column_id");
+        query.bind(quote_table(schema, table));
+        let rows = query.query(client).await?.into_first_result().await?;
+        let mut names = Vec::new();
+        for row in rows.iter() {
+            let name: &str = row.get(0).ok_or(TransferError::from_str("Columns select error"))?;
+            names.push(name.to_string());
+        }
+        Ok(names)
+    })
+}
+
+// code page is taken from the format file collation, or from the target column when not specified there
+fn resolve_code_pages(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, schema: &str, table: &str,
+                      format: &BcpFormat) -> Result<BcpFormat, TransferError> {
+    let mut format = format.clone();
+    runtime.block_on(async {
+        for col in format.columns.iter_mut().filter(|c| c.is_char()) {
+            let query = if col.collation.is_empty() {
+                let mut query = tiberius::Query::new("\
+                        select cast(collationproperty(c.collation_name, 'CodePage') as int)
+                        from sys.columns as c
+                        where c.object_id = object_id(@P1)
+                        and c.name = @P2");
+                query.bind(quote_table(schema, table));
+                query.bind(col.name.clone());
+                query
+            } else {
+                let mut query = tiberius::Query::new("select cast(collationproperty(@P1, 'CodePage') as int)");
+                query.bind(col.collation.clone());
+                query
+            };
+            let row = query.query(client).await?.into_row().await?;
+            col.code_page = match row.as_ref().and_then(|r| r.get::<i32, _>(0)) {
+                Some(code_page) if code_page > 0 => code_page as u32,
+                _ => return Err(TransferError::from_string(format!(
+                    "Code page of character data cannot be determined, table: {}.{}, column: {}", schema, table, &col.name)))
+            };
+        }
+        Ok(format)
+    })
+}
+
+pub(super) fn import_native_rows<P: Fn(&str)->(), R: Read>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                                                           schema: &str, table: &str, format: &BcpFormat, reader: &mut R) -> Result<u64, TransferError> {
+    let table_name = quote_table(schema, table);
+    let format = resolve_code_pages(runtime, client, schema, table, format)?;
+    let column_names: Vec<&str> = format.columns.iter().map(|c| c.name.as_str()).collect();
+    // same semantics as 'bcp in' with '-k' and '-E' flags
+    let options = SqlBulkCopyOptions::KeepNulls | SqlBulkCopyOptions::KeepIdentity;
+    runtime.block_on(async {
+        let mut req = client.bulk_insert_with_options(&table_name, &column_names, options, &[]).await?;
+        let mut buf: Vec<u8> = Vec::new();
+        let mut count: u64 = 0;
+        'rows: loop {
+            let mut row = TokenRow::with_capacity(format.columns.len());
+            for (idx, col) in format.columns.iter().enumerate() {
+                let not_null = match read_native_field(reader, &mut buf, col)? {
+                    Some(flag) => flag,
+                    None => {
+                        if 0 == idx {
+                            break 'rows;
+                        }
+                        return Err(TransferError::from_string(format!(
+                            "Unexpected end of data file, table: {}.{}, row: {}", schema, table, count + 1)));
+                    }
+                };
+                let value = if not_null { Some(buf.as_slice()) } else { None };
+                row.push(decode_native_value(col, value)?);
+            }
+            req.send(row).await?;
+            count += 1;
+            if 0 == count % 100000 {
+                progress_fun(&format!("{} rows sent to SQL Server.", count));
+            }
+        }
+        let res = req.finalize().await?;
+        Ok(res.total())
+    })
+}
+
+pub(super) fn run_native_import<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                                                 table: &TableWithSize, bcp_file: &Path, format_file: &Path) -> Result<(), TransferError> {
+    let bcp_filename = bcp_file.file_name().ok_or(
+        TransferError::from_str("Filename error"))?.to_string_lossy().to_string();
+    progress_fun(&format!("Importing file: {}", bcp_filename));
+    let format = BcpFormat::read_file(format_file)?;
+    let file = File::open(bcp_file)?;
+    let mut reader = BufReader::new(file);
+    let count = import_native_rows(progress_fun, runtime, client, &table.schema, &table.table, &format, &mut reader)?;
+    progress_fun(&format!("{} rows copied.", count));
+    Ok(())
+}
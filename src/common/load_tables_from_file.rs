@@ -18,12 +18,35 @@ use super::*;
 
 use std::fs::File;
 use std::io::BufReader;
+use std::io::Read;
 use std::path::Path;
 
 use human_bytes::human_bytes;
 use zip::ZipArchive;
 
-pub fn load_tables_from_file<P: Fn(&str)->()>(progress_fun: &P, file_path: &str) -> Result<Vec<TableWithSize>, TransferError> {
+fn tables_from_entry_names(zip: &mut ZipArchive<BufReader<File>>, file_path: &str) -> Result<Vec<TableWithSize>, TransferError> {
+    let mut tables: Vec<TableWithSize> = Vec::new();
+    for i in 0..zip.len() {
+        let entry = match zip.by_index(i) {
+            Ok(entry) => entry,
+            Err(e) => return Err(TransferError::from_string(format!(
+                "Error opening ZIP file, path: {}, message: {}", file_path, e.to_string())))
+        };
+        if entry.name().ends_with(".bcp.gz") || entry.name().ends_with(".bcp.zstd") || entry.name().ends_with(".bcp") {
+            let name_parts = entry.name().split("/").collect::<Vec<&str>>();
+            let name = name_parts[name_parts.len() - 1];
+            let tab = TableWithSize::new(name, entry.size())?;
+            // parts of the same table are listed as a single table
+            match tables.iter_mut().find(|t| t.schema == tab.schema && t.table == tab.table) {
+                Some(existing) => existing.add_part(&tab),
+                None => tables.push(tab)
+            }
+        }
+    };
+    Ok(tables)
+}
+
+pub fn load_tables_from_file<P: Fn(&str)->()>(progress_fun: &P, file_path: &str, secret: &EncryptionSecret) -> Result<Vec<TableWithSize>, TransferError> {
     if !Path::new(&file_path).exists() {
         return Err(TransferError::from_string(format!(
             "Specified file is not found, path: {}", file_path)));
@@ -39,22 +62,43 @@ pub fn load_tables_from_file<P: Fn(&str)->()>(progress_fun: &P, file_path: &str)
         Err(e) => return Err(TransferError::from_string(format!(
             "Error opening ZIP file, path: {}, message: {}", file_path, e.to_string())))
     };
-    let mut tables: Vec<TableWithSize> = Vec::new();
     progress_fun("Loading tables ...");
-    for i in 0..zip.len() {
-        let entry = match zip.by_index(i) {
-            Ok(entry) => entry,
-            Err(e) => return Err(TransferError::from_string(format!(
-                "Error opening ZIP file, path: {}, message: {}", file_path, e.to_string())))
-        };
-        if entry.name().ends_with(".bcp.gz") || entry.name().ends_with(".bcp.zstd") {
-            let name_parts = entry.name().split("/").collect::<Vec<&str>>();
-            let name = name_parts[name_parts.len() - 1];
-            let tab = TableWithSize::new(name, entry.size())?;
-            progress_fun(&format!("{}.{} {}", &tab.schema, &tab.table, human_bytes(tab.size_bytes as f64)));
-            tables.push(tab);
-        }
+    let dirname: String = match zip.file_names().find(|nm| nm.ends_with("/")) {
+        Some(dirname) => dirname.chars().take(dirname.len() - 1).collect(),
+        None => return Err(TransferError::from_str("Directory entry not found in ZIP file"))
     };
+    let key = archive_key(&mut zip, &dirname, secret)?;
+    let manifest_name = format!("{}/{}", &dirname, MANIFEST_FILENAME);
+    let tables: Vec<TableWithSize> = if zip.file_names().any(|nm| nm == manifest_name) {
+        let entry = zip.by_name(&manifest_name)?;
+        let mut text = String::new();
+        decrypting_reader(entry, key.as_ref(), &manifest_name).read_to_string(&mut text)?;
+        let manifest = Manifest::parse(&text)?;
+        // tables exported without data are listed when their definition is present
+        manifest.tables.iter()
+            .filter(|mt| {
+                let ddl_name = format!("{}/{}.{}.ddl.sql", &dirname, &mt.schema, &mt.table);
+                !mt.data_files.is_empty() || zip.file_names().any(|nm| nm == ddl_name)
+            })
+            .map(|mt| TableWithSize::from_manifest(mt))
+            .collect()
+    } else {
+        // archives created by older versions have no manifest
+        tables_from_entry_names(&mut zip, file_path)?
+    };
+    for tab in tables.iter() {
+        let mut line = format!("{}.{} {}", &tab.schema, &tab.table, human_bytes(tab.size_bytes as f64));
+        if let Some(row_count) = tab.row_count {
+            line.push_str(&format!(", {} rows", row_count));
+        }
+        if !tab.parts.is_empty() {
+            line.push_str(&format!(" in {} parts", tab.parts.len()));
+        }
+        if tab.schema_only {
+            line.push_str(", definition only");
+        }
+        progress_fun(&line);
+    }
 
     Ok(tables)
 }
@@ -0,0 +1,137 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use serde::Deserialize;
+use serde::Serialize;
+use tiberius::Client;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_util::compat::Compat;
+
+// archives without manifest are considered to have format version 1
+pub static MANIFEST_FORMAT_VERSION: u32 = 2;
+pub static MANIFEST_FILENAME: &str = "manifest.json";
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestSource {
+    pub server: String,
+    pub server_version: String,
+    pub database: String,
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestOptions {
+    pub native_tds: bool,
+    pub stream_compression: bool,
+    pub schema_only: bool,
+    pub modules: bool,
+    pub security: bool,
+    pub chunk_rows: i64,
+    pub codec: String,
+    pub compression_level: i32,
+    #[serde(default)]
+    pub encrypted: bool,
+    // empty in manifests written before text format was added, same as 'bcp'
+    #[serde(default)]
+    pub data_format: String,
+    #[serde(default)]
+    pub text_format: String,
+    // data files were written to a directory outside of the export file
+    #[serde(default)]
+    pub external_data: bool,
+    pub filtered_tables: Vec<String>,
+    pub column_selections: Vec<String>,
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestTable {
+    pub schema: String,
+    pub table: String,
+    pub row_count: i64,
+    pub size_bytes: u64,
+    pub parts: Vec<String>,
+    pub data_files: Vec<String>,
+}
+
+// checksum of a data file is computed over its uncompressed contents
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+    #[serde(default)]
+    pub external: bool,
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub tool_version: String,
+    pub format_version: u32,
+    pub source: ManifestSource,
+    pub exported_at: String,
+    pub options: ManifestOptions,
+    pub tables: Vec<ManifestTable>,
+    #[serde(default)]
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn parse(text: &str) -> Result<Self, TransferError> {
+        match serde_json::from_str(text) {
+            Ok(manifest) => Ok(manifest),
+            Err(e) => Err(TransferError::from_string(format!(
+                "Manifest parse error: {}", e)))
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, TransferError> {
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => Ok(json),
+            Err(e) => Err(TransferError::from_string(format!(
+                "Manifest write error: {}", e)))
+        }
+    }
+}
+
+// data files are named 'schema.table[.part].ext[.codec]'
+pub fn data_file_codec(filename: &str) -> Option<CompressionCodec> {
+    let (name, codec) = if let Some(name) = filename.strip_suffix(".zstd") {
+        (name, CompressionCodec::Zstd)
+    } else if let Some(name) = filename.strip_suffix(".gz") {
+        (name, CompressionCodec::Gzip)
+    } else {
+        (filename, CompressionCodec::Uncompressed)
+    };
+    if name.ends_with(".bcp") || name.ends_with(".csv") || name.ends_with(".parquet") || name.ends_with(".data.sql") {
+        Some(codec)
+    } else {
+        None
+    }
+}
+
+pub fn is_data_file(filename: &str) -> bool {
+    data_file_codec(filename).is_some()
+}
+
+pub fn load_server_version(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>) -> Result<String, TransferError> {
+    runtime.block_on(async {
+        let row = tiberius::Query::new("select @@version").query(client).await?.into_row().await?;
+        let version: Option<&str> = row.as_ref().and_then(|r| r.get(0));
+        Ok(version.unwrap_or("").to_string())
+    })
+}
@@ -14,25 +14,131 @@
  * limitations under the License.
  */
 
+mod archive_writer;
+mod bcp_format;
+mod bcp_native;
+mod column_selection;
+mod data_writer;
+mod encryption;
+mod export_journal;
+mod export_native;
+mod export_parquet;
+mod export_sql;
+mod export_text;
+mod import_journal;
+mod import_native;
 pub mod labels;
 mod load_tables_from_db;
 mod load_tables_from_file;
+mod manifest;
+mod named_pipe;
+mod post_data;
+mod reseed;
 mod run_export;
 mod run_import;
+mod security;
+mod signature;
+mod sql_ident;
+mod sql_modules;
+mod table_ddl;
+mod table_dependencies;
+mod table_parts;
 mod table_with_rows_count;
 mod table_with_size;
 mod tds_conn_config;
 mod transfer_error;
+mod verify_archive;
 
+use archive_writer::ArchiveWriter;
+use archive_writer::SpoolWriter;
+use bcp_format::BcpFieldKind;
+use bcp_format::BcpFormat;
+use bcp_format::BcpFormatColumn;
+use bcp_native::decode_native_value;
+use bcp_native::read_native_field;
+use bcp_native::write_native_value;
+use data_writer::DataWriter;
+use encryption::ENCRYPTION_FILENAME;
+use encryption::EncryptingWriter;
+use encryption::EncryptionInfo;
+use encryption::EncryptionKey;
+use encryption::archive_key;
+use encryption::decrypting_reader;
+use encryption::encrypted_len;
+use encryption::from_hex;
+use encryption::to_hex;
+use export_journal::ExportJournal;
+use export_journal::JournalFile;
+use import_journal::ImportJournal;
+use import_journal::archive_identity;
+use manifest::MANIFEST_FILENAME;
+use manifest::MANIFEST_FORMAT_VERSION;
+use manifest::Manifest;
+use manifest::ManifestEntry;
+use manifest::ManifestOptions;
+use manifest::ManifestSource;
+use manifest::ManifestTable;
+use manifest::data_file_codec;
+use manifest::is_data_file;
+use manifest::load_server_version;
+use named_pipe::NamedPipe;
+use named_pipe::release_named_pipe;
+use post_data::PostDataObject;
+use post_data::parse_post_data;
+use post_data::post_data_exists;
+use post_data::run_post_data;
+use reseed::parse_reseed;
+use reseed::run_reseed;
+use security::SecurityKind;
+use security::parse_security;
+use security::principal_exists;
+use security::run_security;
+use signature::SIGNATURE_FILENAME;
+use signature::load_signing_key;
+use signature::load_trusted_key;
+use signature::sign_manifest;
+use signature::verify_signature;
+use sql_ident::quote_ident;
+use sql_ident::quote_literal;
+use sql_ident::quote_table;
+use sql_modules::module_exists;
+use sql_modules::parse_sql_modules;
+use sql_modules::run_sql_modules;
+use table_ddl::create_schema_if_missing;
+use table_ddl::execute_ddl;
+use table_ddl::recreate_table;
+use table_ddl::run_table_ddl;
+use table_ddl::table_exists;
+use table_dependencies::load_table_dependencies;
+use table_dependencies::parent_indices;
+use table_parts::TablePart;
+use table_parts::combine_predicates;
+use table_parts::parse_parts;
+use table_parts::parts_to_text;
+use table_parts::plan_table_parts;
+use verify_archive::entry_sha256;
+use verify_archive::verify_archive_entries;
+
+pub use column_selection::ColumnSelection;
+pub use data_writer::CompressionCodec;
+pub use encryption::EncryptionSecret;
+pub use export_parquet::ParquetFormat;
+pub use export_sql::SqlFormat;
+pub use export_text::TextEncoding;
+pub use export_text::TextFormat;
+pub use export_text::TextQuoting;
 pub use load_tables_from_db::load_tables_from_db;
 pub use load_tables_from_file::load_tables_from_file;
+pub use run_export::DataFormat;
 pub use run_export::ExportArgs;
 pub use run_export::ExportResult;
 pub use run_export::run_export;
 pub use run_import::ImportArgs;
 pub use run_import::ImportResult;
+pub use run_import::ReplacePolicy;
 pub use run_import::run_import;
 pub use table_with_rows_count::TableWithRowsCount;
 pub use table_with_size::TableWithSize;
 pub use tds_conn_config::TdsConnConfig;
 pub use transfer_error::TransferError;
+pub use verify_archive::run_verify;
@@ -0,0 +1,83 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::io::AsRawHandle;
+use std::os::windows::io::FromRawHandle;
+use std::ptr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use winapi::shared::winerror::ERROR_PIPE_CONNECTED;
+use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+use winapi::um::namedpipeapi::ConnectNamedPipe;
+use winapi::um::namedpipeapi::CreateNamedPipeW;
+use winapi::um::winbase::PIPE_ACCESS_INBOUND;
+use winapi::um::winbase::PIPE_READMODE_BYTE;
+use winapi::um::winbase::PIPE_TYPE_BYTE;
+use winapi::um::winbase::PIPE_WAIT;
+
+static PIPE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+pub struct NamedPipe {
+    pub name: String,
+    file: File,
+}
+
+impl NamedPipe {
+    pub fn create() -> Result<Self, TransferError> {
+        let num = PIPE_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let name = format!("\\\\.\\pipe\\wdb_transfer_{}_{}", std::process::id(), num);
+        let wide: Vec<u16> = OsStr::new(&name).encode_wide().chain(Some(0)).collect();
+        let handle = unsafe {
+            CreateNamedPipeW(wide.as_ptr(), PIPE_ACCESS_INBOUND, PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT,
+                             1, 0, 1 << 20, 0, ptr::null_mut())
+        };
+        if INVALID_HANDLE_VALUE == handle {
+            return Err(TransferError::from_string(format!(
+                "Error creating named pipe, name: {}, message: {}", name, io::Error::last_os_error())));
+        }
+        let file = unsafe { File::from_raw_handle(handle as _) };
+        Ok(Self {
+            name,
+            file
+        })
+    }
+
+    // blocks until the writer opens the pipe
+    pub fn connect(self) -> Result<File, TransferError> {
+        let res = unsafe { ConnectNamedPipe(self.file.as_raw_handle() as _, ptr::null_mut()) };
+        if 0 == res {
+            let err = io::Error::last_os_error();
+            if Some(ERROR_PIPE_CONNECTED as i32) != err.raw_os_error() {
+                return Err(TransferError::from_string(format!(
+                    "Error connecting named pipe, name: {}, message: {}", self.name, err)));
+            }
+        }
+        Ok(self.file)
+    }
+}
+
+// releases the reader waiting in 'connect' when the writer failed to open the pipe
+pub fn release_named_pipe(name: &str) {
+    let _ = OpenOptions::new().write(true).open(name);
+}
@@ -0,0 +1,328 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use tiberius::Client;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_util::compat::Compat;
+
+// variants are listed in the order they are applied on import
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum PostDataKind {
+    PrimaryKey,
+    UniqueConstraint,
+    Index,
+    CheckConstraint,
+    ForeignKey,
+}
+
+impl PostDataKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PostDataKind::PrimaryKey => "primary_key",
+            PostDataKind::UniqueConstraint => "unique_constraint",
+            PostDataKind::Index => "index",
+            PostDataKind::CheckConstraint => "check_constraint",
+            PostDataKind::ForeignKey => "foreign_key",
+        }
+    }
+
+    fn from_label(label: &str) -> Option<Self> {
+        match label {
+            "primary_key" => Some(PostDataKind::PrimaryKey),
+            "unique_constraint" => Some(PostDataKind::UniqueConstraint),
+            "index" => Some(PostDataKind::Index),
+            "check_constraint" => Some(PostDataKind::CheckConstraint),
+            "foreign_key" => Some(PostDataKind::ForeignKey),
+            _ => None
+        }
+    }
+
+    pub fn is_index(&self) -> bool {
+        match self {
+            PostDataKind::PrimaryKey | PostDataKind::UniqueConstraint | PostDataKind::Index => true,
+            _ => false
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PostDataObject {
+    pub kind: PostDataKind,
+    pub schema: String,
+    pub table: String,
+    pub name: String,
+    pub sql: String,
+}
+
+impl PostDataObject {
+    fn new(kind: PostDataKind, schema: &str, table: &str, name: &str, sql: String) -> Self {
+        Self {
+            kind,
+            schema: schema.to_string(),
+            table: table.to_string(),
+            name: name.to_string(),
+            sql
+        }
+    }
+}
+
+fn column_list(cols: &Vec<String>) -> String {
+    cols.iter().map(|c| quote_ident(c)).collect::<Vec<String>>().join(", ")
+}
+
+async fn load_indexes(client: &mut Client<Compat<TcpStream>>, schema: &str, table: &str,
+                      objects: &mut Vec<PostDataObject>) -> Result<(), TransferError> {
+    let mut query = tiberius::Query::new("\
+            select
+                i.name,
+                i.type_desc,
+                i.is_unique,
+                i.is_primary_key,
+                i.is_unique_constraint,
+                i.filter_definition,
+                c.name as column_name,
+                ic.is_descending_key,
+                ic.is_included_column,
+                i.is_disabled
+            from sys.indexes as i
+            join sys.index_columns as ic
+                on ic.object_id = i.object_id
+                and ic.index_id = i.index_id
+            join sys.columns as c
+                on c.object_id = ic.object_id
+                and c.column_id = ic.column_id
+            where i.object_id = object_id(@P1)
+            and i.type_desc in ('CLUSTERED', 'NONCLUSTERED')
+            and i.is_hypothetical = 0
+            order by i.index_id, ic.is_included_column, ic.key_ordinal, ic.index_column_id");
+    let table_name = quote_table(schema, table);
+    query.bind(table_name.clone());
+    let rows = query.query(client).await?.into_first_result().await?;
+    let msg = "Indexes select error";
+    let mut idx = 0;
+    while idx < rows.len() {
+        let name: &str = rows[idx].get(0).ok_or(TransferError::from_str(msg))?;
+        let type_desc: &str = rows[idx].get(1).ok_or(TransferError::from_str(msg))?;
+        let is_unique: bool = rows[idx].get(2).ok_or(TransferError::from_str(msg))?;
+        let is_primary_key: bool = rows[idx].get(3).ok_or(TransferError::from_str(msg))?;
+        let is_unique_constraint: bool = rows[idx].get(4).ok_or(TransferError::from_str(msg))?;
+        let filter: Option<&str> = rows[idx].get(5);
+        let disabled: bool = rows[idx].get(9).unwrap_or(false);
+        let mut key_cols = Vec::new();
+        let mut included_cols = Vec::new();
+        while idx < rows.len() && Some(name) == rows[idx].get::<&str, _>(0) {
+            let column_name: &str = rows[idx].get(6).ok_or(TransferError::from_str(msg))?;
+            let descending: bool = rows[idx].get(7).unwrap_or(false);
+            let included: bool = rows[idx].get(8).unwrap_or(false);
+            if included {
+                included_cols.push(column_name.to_string());
+            } else if descending {
+                key_cols.push(format!("{} desc", quote_ident(column_name)));
+            } else {
+                key_cols.push(quote_ident(column_name));
+            }
+            idx += 1;
+        }
+        let kind_sql = type_desc.to_lowercase();
+        let (kind, mut sql) = if is_primary_key || is_unique_constraint {
+            let (kind, keyword) = if is_primary_key {
+                (PostDataKind::PrimaryKey, "primary key")
+            } else {
+                (PostDataKind::UniqueConstraint, "unique")
+            };
+            (kind, format!("alter table {} add constraint {} {} {} ({})",
+                           &table_name, quote_ident(name), keyword, kind_sql, key_cols.join(", ")))
+        } else {
+            let mut sql = format!("create {}{} index {} on {} ({})",
+                                  if is_unique { "unique " } else { "" }, kind_sql,
+                                  quote_ident(name), &table_name, key_cols.join(", "));
+            if !included_cols.is_empty() {
+                sql.push_str(&format!(" include ({})", column_list(&included_cols)));
+            }
+            if let Some(filter) = filter {
+                sql.push_str(&format!(" where {}", filter));
+            }
+            (PostDataKind::Index, sql)
+        };
+        if disabled {
+            sql.push_str(&format!("\r\nalter index {} on {} disable", quote_ident(name), &table_name));
+        }
+        objects.push(PostDataObject::new(kind, schema, table, name, sql));
+    }
+    Ok(())
+}
+
+async fn load_check_constraints(client: &mut Client<Compat<TcpStream>>, schema: &str, table: &str,
+                                objects: &mut Vec<PostDataObject>) -> Result<(), TransferError> {
+    let mut query = tiberius::Query::new("\
+            select
+                cc.name,
+                cc.definition,
+                cc.is_disabled
+            from sys.check_constraints as cc
+            where cc.parent_object_id = object_id(@P1)
+            order by cc.name");
+    let table_name = quote_table(schema, table);
+    query.bind(table_name.clone());
+    let rows = query.query(client).await?.into_first_result().await?;
+    let msg = "Check constraints select error";
+    for row in rows.iter() {
+        let name: &str = row.get(0).ok_or(TransferError::from_str(msg))?;
+        let definition: &str = row.get(1).ok_or(TransferError::from_str(msg))?;
+        let disabled: bool = row.get(2).unwrap_or(false);
+        let sql = if disabled {
+            format!("alter table {} with nocheck add constraint {} check {}\r\nalter table {} nocheck constraint {}",
+                    &table_name, quote_ident(name), definition, &table_name, quote_ident(name))
+        } else {
+            format!("alter table {} add constraint {} check {}", &table_name, quote_ident(name), definition)
+        };
+        objects.push(PostDataObject::new(PostDataKind::CheckConstraint, schema, table, name, sql));
+    }
+    Ok(())
+}
+
+async fn load_foreign_keys(client: &mut Client<Compat<TcpStream>>, schema: &str, table: &str,
+                           objects: &mut Vec<PostDataObject>) -> Result<(), TransferError> {
+    let mut query = tiberius::Query::new("\
+            select
+                fk.name,
+                schema_name(rt.schema_id) as referenced_schema,
+                rt.name as referenced_table,
+                fk.delete_referential_action_desc,
+                fk.update_referential_action_desc,
+                fk.is_disabled,
+                pc.name as parent_column,
+                rc.name as referenced_column
+            from sys.foreign_keys as fk
+            join sys.tables as rt
+                on rt.object_id = fk.referenced_object_id
+            join sys.foreign_key_columns as fkc
+                on fkc.constraint_object_id = fk.object_id
+            join sys.columns as pc
+                on pc.object_id = fkc.parent_object_id
+                and pc.column_id = fkc.parent_column_id
+            join sys.columns as rc
+                on rc.object_id = fkc.referenced_object_id
+                and rc.column_id = fkc.referenced_column_id
+            where fk.parent_object_id = object_id(@P1)
+            order by fk.name, fkc.constraint_column_id");
+    let table_name = quote_table(schema, table);
+    query.bind(table_name.clone());
+    let rows = query.query(client).await?.into_first_result().await?;
+    let msg = "Foreign keys select error";
+    let mut idx = 0;
+    while idx < rows.len() {
+        let name: &str = rows[idx].get(0).ok_or(TransferError::from_str(msg))?;
+        let ref_schema: &str = rows[idx].get(1).ok_or(TransferError::from_str(msg))?;
+        let ref_table: &str = rows[idx].get(2).ok_or(TransferError::from_str(msg))?;
+        let on_delete: &str = rows[idx].get(3).unwrap_or("NO_ACTION");
+        let on_update: &str = rows[idx].get(4).unwrap_or("NO_ACTION");
+        let disabled: bool = rows[idx].get(5).unwrap_or(false);
+        let mut parent_cols = Vec::new();
+        let mut ref_cols = Vec::new();
+        while idx < rows.len() && Some(name) == rows[idx].get::<&str, _>(0) {
+            let parent_col: &str = rows[idx].get(6).ok_or(TransferError::from_str(msg))?;
+            let ref_col: &str = rows[idx].get(7).ok_or(TransferError::from_str(msg))?;
+            parent_cols.push(parent_col.to_string());
+            ref_cols.push(ref_col.to_string());
+            idx += 1;
+        }
+        let mut sql = format!("alter table {} {} add constraint {} foreign key ({}) references {} ({}) on delete {} on update {}",
+                              &table_name, if disabled { "with nocheck" } else { "with check" }, quote_ident(name),
+                              column_list(&parent_cols), quote_table(ref_schema, ref_table), column_list(&ref_cols),
+                              on_delete.replace("_", " ").to_lowercase(), on_update.replace("_", " ").to_lowercase());
+        if disabled {
+            sql.push_str(&format!("\r\nalter table {} nocheck constraint {}", &table_name, quote_ident(name)));
+        }
+        objects.push(PostDataObject::new(PostDataKind::ForeignKey, schema, table, name, sql));
+    }
+    Ok(())
+}
+
+pub fn load_post_data(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                      schema: &str, table: &str) -> Result<Vec<PostDataObject>, TransferError> {
+    runtime.block_on(async {
+        let mut objects = Vec::new();
+        load_indexes(client, schema, table, &mut objects).await?;
+        load_check_constraints(client, schema, table, &mut objects).await?;
+        load_foreign_keys(client, schema, table, &mut objects).await?;
+        Ok(objects)
+    })
+}
+
+pub fn post_data_to_sql(objects: &Vec<PostDataObject>) -> String {
+    let mut res = String::new();
+    for obj in objects.iter() {
+        res.push_str(&format!("-- object: {} {}\r\n{}\r\nGO\r\n", obj.kind.label(), &obj.name, &obj.sql));
+    }
+    res
+}
+
+pub fn parse_post_data(schema: &str, table: &str, text: &str) -> Result<Vec<PostDataObject>, TransferError> {
+    let mut objects = Vec::new();
+    let mut header: Option<(PostDataKind, String)> = None;
+    let mut lines: Vec<&str> = Vec::new();
+    for ln in text.lines() {
+        if let Some(rest) = ln.strip_prefix("-- object: ") {
+            let (label, name) = rest.split_once(' ').unwrap_or((rest, ""));
+            let kind = match PostDataKind::from_label(label) {
+                Some(kind) => kind,
+                None => return Err(TransferError::from_string(format!(
+                    "Unsupported object type: {}, table: {}.{}", label, schema, table)))
+            };
+            header = Some((kind, name.to_string()));
+            lines.clear();
+        } else if "GO" == ln.trim() {
+            if let Some((kind, name)) = header.take() {
+                objects.push(PostDataObject::new(kind, schema, table, &name, lines.join("\r\n")));
+            }
+            lines.clear();
+        } else {
+            lines.push(ln);
+        }
+    }
+    Ok(objects)
+}
+
+pub fn post_data_exists(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                        obj: &PostDataObject) -> Result<bool, TransferError> {
+    let sql = if obj.kind.is_index() {
+        "select count(*) from sys.indexes where object_id = object_id(@P1) and name = @P2"
+    } else {
+        "select count(*) from sys.objects where parent_object_id = object_id(@P1) and name = @P2"
+    };
+    runtime.block_on(async {
+        let mut query = tiberius::Query::new(sql);
+        query.bind(quote_table(&obj.schema, &obj.table));
+        query.bind(obj.name.clone());
+        let row = query.query(client).await?.into_row().await?;
+        Ok(row.and_then(|r| r.get::<i32, _>(0)).unwrap_or(0) > 0)
+    })
+}
+
+pub fn run_post_data<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                                      schema: &str, table: &str) -> Result<Option<(String, String)>, TransferError> {
+    progress_fun(&format!("Scripting indexes and constraints: {}.{}", schema, table));
+    let objects = load_post_data(runtime, client, schema, table)?;
+    if objects.is_empty() {
+        return Ok(None);
+    }
+    let post_filename = format!("{}.{}.post.sql", schema, table);
+    Ok(Some((post_filename, post_data_to_sql(&objects))))
+}
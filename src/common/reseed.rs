@@ -0,0 +1,269 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use tiberius::Client;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_util::compat::Compat;
+
+#[derive(Default, Clone, Debug)]
+pub struct ReseedStatement {
+    pub is_identity: bool,
+    pub schema: String,
+    pub name: String,
+    pub sql: String,
+}
+
+impl ReseedStatement {
+    pub fn label(&self) -> String {
+        format!("{} {}.{}", if self.is_identity { "identity" } else { "sequence" }, &self.schema, &self.name)
+    }
+}
+
+// identity is only raised, it is left as is when the target table
+// already has a higher identity value or higher keys
+async fn load_identities(client: &mut Client<Compat<TcpStream>>, tables: &Vec<(String, String)>) -> Result<Vec<ReseedStatement>, TransferError> {
+    let query = tiberius::Query::new("\
+            select
+                schema_name(t.schema_id) as schema_name,
+                t.name,
+                cast(ident_current(quotename(schema_name(t.schema_id)) + '.' + quotename(t.name)) as varchar(64)) as current_value,
+                ic.name as column_name,
+                cast(ic.increment_value as varchar(64)) as increment
+            from sys.identity_columns as ic
+            join sys.tables as t
+                on t.object_id = ic.object_id
+            order by schema_name, t.name");
+    let rows = query.query(client).await?.into_first_result().await?;
+    let msg = "Identity columns select error";
+    let mut res = Vec::new();
+    for row in rows.iter() {
+        let schema: &str = row.get(0).ok_or(TransferError::from_str(msg))?;
+        let table: &str = row.get(1).ok_or(TransferError::from_str(msg))?;
+        if !tables.iter().any(|(s, t)| s == schema && t == table) {
+            continue;
+        }
+        let current: Option<&str> = row.get(2);
+        let column: &str = row.get(3).ok_or(TransferError::from_str(msg))?;
+        let increment: &str = row.get(4).ok_or(TransferError::from_str(msg))?;
+        if let Some(current) = current {
+            let descending = increment.starts_with('-');
+            let table_name = quote_table(schema, table);
+            let sql = format!("declare @current numeric(38, 0) = ident_current({})\r\n\
+                               declare @bound numeric(38, 0) = (select {}({}) from {})\r\n\
+                               if (@current is null or @current {} {}) and (@bound is null or @bound {} {})\r\n    \
+                               dbcc checkident ({}, reseed, {})",
+                              quote_literal(&table_name),
+                              if descending { "min" } else { "max" }, quote_ident(column), &table_name,
+                              if descending { ">" } else { "<" }, current,
+                              if descending { ">" } else { "<" }, current,
+                              quote_literal(&table_name), current);
+            res.push(ReseedStatement {
+                is_identity: true,
+                schema: schema.to_string(),
+                name: table.to_string(),
+                sql
+            });
+        }
+    }
+    Ok(res)
+}
+
+// next value to be returned by the sequence on the target, unused sequence
+// restarts at its current value, exhausted cycling sequence wraps around,
+// returns true when the restart value must be consumed to leave the sequence exhausted
+fn restart_value(current: &str, increment: &str, minimum: &str, maximum: &str,
+                 cycling: bool, exhausted: bool, used: bool) -> Result<(String, bool), TransferError> {
+    let current: i128 = current.parse()?;
+    let increment: i128 = increment.parse()?;
+    let minimum: i128 = minimum.parse()?;
+    let maximum: i128 = maximum.parse()?;
+    if !used {
+        return Ok((current.to_string(), false));
+    }
+    let next = current + increment;
+    if exhausted || next > maximum || next < minimum {
+        if cycling {
+            let wrapped = if increment > 0 { minimum } else { maximum };
+            Ok((wrapped.to_string(), false))
+        } else {
+            Ok((current.to_string(), true))
+        }
+    } else {
+        Ok((next.to_string(), false))
+    }
+}
+
+// sequences referenced from the defaults, constraints and triggers of the exported tables
+async fn load_used_sequences(client: &mut Client<Compat<TcpStream>>, tables: &Vec<(String, String)>) -> Result<Vec<i32>, TransferError> {
+    let query = tiberius::Query::new("\
+            select distinct
+                d.referenced_id,
+                schema_name(t.schema_id) as schema_name,
+                t.name
+            from sys.sql_expression_dependencies as d
+            join sys.sequences as s
+                on s.object_id = d.referenced_id
+            join sys.objects as o
+                on o.object_id = d.referencing_id
+            join sys.tables as t
+                on t.object_id = o.parent_object_id");
+    let rows = query.query(client).await?.into_first_result().await?;
+    let msg = "Sequence dependencies select error";
+    let mut res = Vec::new();
+    for row in rows.iter() {
+        let id: i32 = row.get(0).ok_or(TransferError::from_str(msg))?;
+        let schema: &str = row.get(1).ok_or(TransferError::from_str(msg))?;
+        let table: &str = row.get(2).ok_or(TransferError::from_str(msg))?;
+        if tables.iter().any(|(s, t)| s == schema && t == table) && !res.contains(&id) {
+            res.push(id);
+        }
+    }
+    Ok(res)
+}
+
+// sequence is only raised, it is left as is when the target sequence
+// is exhausted or would already return a higher value
+async fn load_sequences(client: &mut Client<Compat<TcpStream>>, tables: &Vec<(String, String)>) -> Result<Vec<ReseedStatement>, TransferError> {
+    let used_sequences = load_used_sequences(client, tables).await?;
+    let query = tiberius::Query::new("\
+            select
+                schema_name(s.schema_id) as schema_name,
+                s.name,
+                type_name(s.user_type_id) as type_name,
+                cast(s.precision as int) as precision,
+                cast(s.start_value as varchar(64)) as start_value,
+                cast(s.increment as varchar(64)) as increment,
+                cast(s.minimum_value as varchar(64)) as minimum_value,
+                cast(s.maximum_value as varchar(64)) as maximum_value,
+                s.is_cycling,
+                cast(s.current_value as varchar(64)) as current_value,
+                s.is_exhausted,
+                cast(s.last_used_value as varchar(64)) as last_used_value,
+                s.object_id
+            from sys.sequences as s
+            order by schema_name, s.name");
+    let rows = query.query(client).await?.into_first_result().await?;
+    let msg = "Sequences select error";
+    let mut res = Vec::new();
+    for row in rows.iter() {
+        let schema: &str = row.get(0).ok_or(TransferError::from_str(msg))?;
+        let name: &str = row.get(1).ok_or(TransferError::from_str(msg))?;
+        let type_name: &str = row.get(2).ok_or(TransferError::from_str(msg))?;
+        let precision: i32 = row.get(3).ok_or(TransferError::from_str(msg))?;
+        let start: &str = row.get(4).ok_or(TransferError::from_str(msg))?;
+        let increment: &str = row.get(5).ok_or(TransferError::from_str(msg))?;
+        let minimum: &str = row.get(6).ok_or(TransferError::from_str(msg))?;
+        let maximum: &str = row.get(7).ok_or(TransferError::from_str(msg))?;
+        let cycling: bool = row.get(8).unwrap_or(false);
+        let current: &str = row.get(9).ok_or(TransferError::from_str(msg))?;
+        let exhausted: bool = row.get(10).unwrap_or(false);
+        // last used value is null until the first value is generated
+        let last_used: Option<&str> = row.get(11);
+        let id: i32 = row.get(12).ok_or(TransferError::from_str(msg))?;
+        if !used_sequences.contains(&id) {
+            continue;
+        }
+        let seq_type = match type_name {
+            "decimal" | "numeric" => format!("{}({}, 0)", type_name, precision),
+            _ => type_name.to_string()
+        };
+        let seq_name = quote_table(schema, name);
+        let (restart, consume) = restart_value(current, increment, minimum, maximum, cycling, exhausted, last_used.is_some())?;
+        let mut sql = format!("if object_id({}, 'SO') is null\r\n    \
+                               exec({})\r\n\
+                               if exists (select 1 from sys.sequences\r\n    \
+                               where object_id = object_id({})\r\n    \
+                               and is_exhausted = 0\r\n    \
+                               and case when last_used_value is null then cast(current_value as numeric(38, 0))\r\n    \
+                               else cast(current_value as numeric(38, 0)) + cast(increment as numeric(38, 0)) end {} {})\r\n\
+                               begin\r\n    \
+                               alter sequence {} restart with {}",
+                              quote_literal(&seq_name),
+                              quote_literal(&format!("create sequence {} as {} start with {} increment by {} minvalue {} maxvalue {} {}",
+                                                     &seq_name, seq_type, start, increment, minimum, maximum,
+                                                     if cycling { "cycle" } else { "no cycle" })),
+                              quote_literal(&seq_name),
+                              if increment.starts_with('-') { ">" } else { "<" }, &restart,
+                              &seq_name, &restart);
+        if consume {
+            sql.push_str(&format!("\r\n    declare @exhausted {}\r\n    set @exhausted = next value for {}", seq_type, &seq_name));
+        }
+        sql.push_str("\r\nend");
+        res.push(ReseedStatement {
+            is_identity: false,
+            schema: schema.to_string(),
+            name: name.to_string(),
+            sql
+        });
+    }
+    Ok(res)
+}
+
+pub fn reseed_to_sql(statements: &Vec<ReseedStatement>) -> String {
+    let mut res = String::new();
+    for st in statements.iter() {
+        let kind = if st.is_identity { "identity" } else { "sequence" };
+        res.push_str(&format!("-- {}: {}\t{}\r\n{}\r\nGO\r\n", kind, &st.schema, &st.name, &st.sql));
+    }
+    res
+}
+
+pub fn parse_reseed(text: &str) -> Result<Vec<ReseedStatement>, TransferError> {
+    let mut statements = Vec::new();
+    let mut current: Option<ReseedStatement> = None;
+    let mut lines: Vec<&str> = Vec::new();
+    for ln in text.lines() {
+        let header = match ln.strip_prefix("-- identity: ") {
+            Some(rest) => Some((true, rest)),
+            None => ln.strip_prefix("-- sequence: ").map(|rest| (false, rest))
+        };
+        if let Some((is_identity, rest)) = header {
+            let (schema, name) = rest.split_once('\t').ok_or(TransferError::from_string(format!(
+                "Invalid reseed header: {}", ln)))?;
+            current = Some(ReseedStatement {
+                is_identity,
+                schema: schema.to_string(),
+                name: name.to_string(),
+                sql: String::new()
+            });
+            lines.clear();
+        } else if "GO" == ln.trim() {
+            if let Some(mut st) = current.take() {
+                st.sql = lines.join("\r\n");
+                statements.push(st);
+            }
+            lines.clear();
+        } else {
+            lines.push(ln);
+        }
+    }
+    Ok(statements)
+}
+
+pub fn run_reseed<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                                   tables: &Vec<(String, String)>) -> Result<(String, String), TransferError> {
+    progress_fun("Recording identity and sequence values ...");
+    let statements = runtime.block_on(async {
+        let mut statements = load_identities(client, tables).await?;
+        statements.extend(load_sequences(client, tables).await?);
+        Ok::<Vec<ReseedStatement>, TransferError>(statements)
+    })?;
+    let reseed_filename = "reseed.sql".to_string();
+    Ok((reseed_filename, reseed_to_sql(&statements)))
+}
@@ -16,16 +16,32 @@
 
 use super::*;
 
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::ffi::OsStr;
 use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::io::BufWriter;
+use std::io::Write;
 use std::os::windows::process::CommandExt;
 use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
 
+use ed25519_dalek::SigningKey;
 use regex::Regex;
+use sha2::Digest;
+use sha2::Sha256;
+use tiberius::Client;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_util::compat::Compat;
 
 #[derive(Default, Clone)]
 pub struct ExportArgs {
@@ -33,6 +49,59 @@ pub struct ExportArgs {
     pub tables: Vec<TableWithRowsCount>,
     pub parent_dir: String,
     pub dest_filename: String,
+    pub native_tds: bool,
+    pub jobs: usize,
+    pub stream_compression: bool,
+    pub resume: bool,
+    pub columns: Vec<ColumnSelection>,
+    pub schema_only: bool,
+    pub export_modules: bool,
+    pub export_security: bool,
+    // tables with more rows are exported in parts, zero disables splitting
+    pub chunk_rows: i64,
+    pub codec: CompressionCodec,
+    pub compression_level: i32,
+    // zero disables zstd worker threads
+    pub compression_threads: u32,
+    // passphrase or key file, entries are not encrypted when empty
+    pub encryption: EncryptionSecret,
+    // path to Ed25519 private key, manifest is not signed when empty
+    pub signing_key_file: String,
+    pub data_format: DataFormat,
+    // data files are moved to this directory instead of the export file when not empty
+    pub data_dir: String,
+}
+
+// text, Parquet and SQL data is always read using TDS connection
+#[derive(Default, Clone, PartialEq, Debug)]
+pub enum DataFormat {
+    #[default]
+    Bcp,
+    Text(TextFormat),
+    Parquet(ParquetFormat),
+    Sql(SqlFormat),
+}
+
+impl DataFormat {
+    pub fn label(&self) -> &'static str {
+        match self {
+            DataFormat::Bcp => "bcp",
+            DataFormat::Text(_) => "csv",
+            DataFormat::Parquet(_) => "parquet",
+            DataFormat::Sql(_) => "sql",
+        }
+    }
+
+    // data file name extension before the compression suffix
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DataFormat::Bcp => ".bcp",
+            DataFormat::Text(_) => ".csv",
+            DataFormat::Parquet(_) => ".parquet",
+            // '.sql' alone is used by table definitions and other scripts
+            DataFormat::Sql(_) => ".data.sql",
+        }
+    }
 }
 
 #[derive(Default)]
@@ -54,8 +123,26 @@ impl ExportResult {
     }
 }
 
-fn strip_collation_from_format_file(dest_dir: &str, format_filename: &str) -> Result<(), TransferError> {
-    let format_path = Path::new(dest_dir).join(&format_filename);
+struct ExportFile {
+    archive: ArchiveWriter,
+    journal: ExportJournal,
+    data_dir: String,
+}
+
+struct ExportTask {
+    table_idx: usize,
+    part: Option<TablePart>,
+}
+
+#[derive(Default)]
+struct ExportSchedule {
+    pending: VecDeque<ExportTask>,
+    running: usize,
+    failed: bool,
+}
+
+fn strip_collation_from_format_file(work_dir: &str, format_filename: &str) -> Result<(), TransferError> {
+    let format_path = Path::new(work_dir).join(&format_filename);
     let bytes = match fs::read(&format_path) {
         Ok(bytes) => bytes,
         Err(e) => return Err(TransferError::from_string(format!(
@@ -86,16 +173,15 @@ fn strip_collation_from_format_file(dest_dir: &str, format_filename: &str) -> Re
     Ok(())
 }
 
-fn run_bcp_format<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, dest_dir: &str,
-                  dbname: &str, schema: &str, table: &str) -> Result<String, TransferError> {
+fn run_bcp_format<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, work_dir: &str,
+                  dbname: &str, schema: &str, table: &str, format_filename: &str) -> Result<(), TransferError> {
     progress_fun(&format!("Creating bcp format file: {}.{}", schema, table));
-    let format_filename = format!("{}.{}.xml", schema, table);
     let mut args: Vec<String> = vec!(
         format!("[{}].[{}].[{}]", dbname, schema, table),
         "format".to_string(),
         "nul".to_string(),
         "-f".to_string(),
-        format_filename.clone(),
+        format_filename.to_string(),
         "-x".to_string(),
         "-n".to_string(),
         "-k".to_string(),
@@ -117,7 +203,7 @@ fn run_bcp_format<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, dest_di
         args.push(cc.password.clone());
     }
     let cmd = duct::cmd("bcp.exe", args)
-        .dir(dest_dir)
+        .dir(work_dir)
         .stdin_null()
         .stderr_to_stdout()
         .stdout_capture()
@@ -157,19 +243,22 @@ fn run_bcp_format<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, dest_di
             "bcp process failure", e.to_string()))
     }
 
-    strip_collation_from_format_file(dest_dir, &format_filename)?;
-
-    Ok(format_filename)
+    strip_collation_from_format_file(work_dir, format_filename)
 }
 
-fn run_bcp_data<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, dest_dir: &str,
-                dbname: &str, schema: &str, table: &str, format_filename: &str) -> Result<String, TransferError> {
-    progress_fun(&format!("Exporting data: {}.{}", schema, table));
-    let data_filename = format!("{}.{}.bcp", schema, table);
+fn run_bcp_data<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, work_dir: &str,
+                dbname: &str, schema: &str, table: &str, query: &str, format_filename: &str, data_filename: &str) -> Result<i64, TransferError> {
+    let (source, direction) = if query.is_empty() {
+        progress_fun(&format!("Exporting data: {}.{}", schema, table));
+        (format!("[{}].[{}].[{}]", dbname, schema, table), "out")
+    } else {
+        progress_fun(&format!("Exporting data: {}.{}, query: {}", schema, table, query));
+        (query.to_string(), "queryout")
+    };
     let mut args: Vec<String> = vec!(
-        format!("[{}].[{}].[{}]", dbname, schema, table),
-        "out".to_string(),
-        data_filename.clone(),
+        source,
+        direction.to_string(),
+        data_filename.to_string(),
         "-f".to_string(),
         format_filename.to_string(),
         "-k".to_string(),
@@ -191,7 +280,7 @@ fn run_bcp_data<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, dest_dir:
         args.push(cc.password.clone());
     }
     let cmd = duct::cmd("bcp.exe", args)
-        .dir(dest_dir)
+        .dir(work_dir)
         .stdin_null()
         .stderr_to_stdout()
         .stdout_capture()
@@ -206,6 +295,8 @@ fn run_bcp_data<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, dest_dir:
             "bcp process spawn failure", e.to_string()))
     };
     let mut buf_reader = BufReader::new(&reader);
+    // row count is taken from the 'N rows copied.' line, it is unknown if bcp output is localized
+    let mut row_count = -1;
     loop {
         let mut buf = vec!();
         match buf_reader.read_until(b'\n', &mut buf) {
@@ -215,6 +306,9 @@ fn run_bcp_data<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, dest_dir:
                 }
                 if buf.len() >= 2 {
                     let ln = String::from_utf8_lossy(&buf[0..buf.len() - 2]);
+                    if let Some(count) = ln.trim().strip_suffix(" rows copied.") {
+                        row_count = count.parse().unwrap_or(-1);
+                    }
                     progress_fun(&ln);
                 }
             },
@@ -231,65 +325,757 @@ fn run_bcp_data<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, dest_dir:
             "bcp process failure", e.to_string()))
     }
 
-    Ok(data_filename)
+    Ok(row_count)
+}
+
+// data is spooled for an export file entry, or is written into a file in the data directory
+enum DataSink {
+    Spool(SpoolWriter),
+    File(PathBuf, BufWriter<File>, Sha256),
+}
+
+impl DataSink {
+    // returns the size and SHA-256 of the written data
+    fn finish(self) -> Result<(u64, String), TransferError> {
+        match self {
+            DataSink::Spool(writer) => writer.finish(),
+            DataSink::File(path, mut writer, hasher) => {
+                writer.flush()?;
+                std::mem::drop(writer);
+                Ok((fs::metadata(&path)?.len(), format!("{:x}", hasher.finalize())))
+            }
+        }
+    }
+
+    fn abort(self) -> Result<(), TransferError> {
+        match self {
+            DataSink::Spool(writer) => writer.abort(),
+            DataSink::File(path, writer, _) => {
+                std::mem::drop(writer);
+                fs::remove_file(&path)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Write for DataSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            DataSink::Spool(writer) => writer.write(buf),
+            DataSink::File(_, writer, hasher) => {
+                let len = writer.write(buf)?;
+                hasher.update(&buf[..len]);
+                Ok(len)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            DataSink::Spool(writer) => writer.flush(),
+            DataSink::File(_, writer, _) => writer.flush()
+        }
+    }
 }
 
-fn compress_bcp_file<P: Fn(&str)->()>(progress_fun: &P, dest_dir: &str,
-                     data_filename: &str) -> Result<String, TransferError> {
+// runs the data function and finishes the sink, partially written data is removed on failure,
+// SHA-256 of the written data is recorded when the data is not wrapped into the data file codec
+fn write_data_sink<F>(mut sink: DataSink, data_filename: &str, write_data: F) -> Result<JournalFile, TransferError>
+where F: FnOnce(&mut DataSink) -> Result<(i64, Option<String>), TransferError> {
+    let (row_count, data_sha256) = match write_data(&mut sink) {
+        Ok(written) => written,
+        Err(e) => {
+            let _ = sink.abort();
+            return Err(e);
+        }
+    };
+    let (size, sink_sha256) = sink.finish()?;
+    Ok(JournalFile {
+        filename: data_filename.to_string(),
+        size,
+        sha256: data_sha256.unwrap_or(sink_sha256),
+        row_count
+    })
+}
+
+fn create_data_writer<'a>(eargs: &ExportArgs, sink: &'a mut DataSink) -> Result<DataWriter<&'a mut DataSink>, TransferError> {
+    DataWriter::create(sink, eargs.codec, eargs.compression_level, eargs.compression_threads)
+}
+
+// data file name with the compression suffix, Parquet file is compressed by pages
+// and is not wrapped into the data file codec
+fn data_entry_name(eargs: &ExportArgs, data_filename: &str) -> String {
+    match &eargs.data_format {
+        DataFormat::Parquet(_) => data_filename.to_string(),
+        _ => format!("{}{}", data_filename, eargs.codec.suffix())
+    }
+}
+
+// data functions return the number of rows written and SHA-256 of the uncompressed data,
+// or none when the data is not wrapped into the data file codec
+fn compress_bcp_file<P: Fn(&str)->()>(progress_fun: &P, eargs: &ExportArgs, work_dir: &str,
+                     data_filename: &str, row_count: i64, sink: &mut DataSink) -> Result<(i64, Option<String>), TransferError> {
     progress_fun(&format!("Compressing: {}", data_filename));
     progress_fun("");
-    let compressed_filename = format!("{}.zstd", data_filename);
-    let src_file_path = Path::new(dest_dir).join(data_filename);
-    let dest_file_path = Path::new(dest_dir).join(&compressed_filename);
-    {
+    let src_file_path = Path::new(work_dir).join(data_filename);
+    let sha256 = {
         let src_file = File::open(&src_file_path)?;
-        let dest_file = File::create(&dest_file_path)?;
         let mut reader = BufReader::new(src_file);
-        let mut writer = zstd::stream::Encoder::new(BufWriter::new(dest_file), 1)?;
-        let _ = writer.multithread(3);
+        let mut writer = create_data_writer(eargs, sink)?;
         std::io::copy(&mut reader, &mut writer)?;
-        let _ = writer.finish()?;
-    }
+        writer.finish()?.1
+    };
     fs::remove_file(&src_file_path)?;
-    Ok(compressed_filename)
+    Ok((row_count, Some(sha256)))
+}
+
+fn stream_bcp_data<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, eargs: &ExportArgs, work_dir: &str, schema: &str, table: &str,
+                   query: &str, format_filename: &str, sink: &mut DataSink) -> Result<(i64, Option<String>), TransferError> {
+    let pipe = NamedPipe::create()?;
+    let pipe_name = pipe.name.clone();
+    progress_fun(&format!("Compressing bcp output from pipe: {}", &pipe_name));
+    let (row_count, sha256) = thread::scope(|scope| {
+        let compress_handle = scope.spawn(move || -> Result<String, TransferError> {
+            let pipe_file = pipe.connect()?;
+            let mut reader = BufReader::new(pipe_file);
+            let mut writer = create_data_writer(eargs, sink)?;
+            std::io::copy(&mut reader, &mut writer)?;
+            Ok(writer.finish()?.1)
+        });
+        let bcp_res = run_bcp_data(progress_fun, cc, work_dir, &eargs.dbname, schema, table, query, format_filename, &pipe_name);
+        release_named_pipe(&pipe_name);
+        let compress_res = match compress_handle.join() {
+            Ok(res) => res,
+            Err(_) => Err(TransferError::from_str("Compression thread failure"))
+        };
+        let row_count = bcp_res?;
+        compress_res.map(|sha256| (row_count, sha256))
+    })?;
+    Ok((row_count, Some(sha256)))
+}
+
+fn stream_native_data<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, eargs: &ExportArgs,
+                      schema: &str, table: &str, predicate: &str, ntf: &export_native::NativeTableFormat, sink: &mut DataSink) -> Result<(i64, Option<String>), TransferError> {
+    progress_fun(&format!("Exporting data: {}.{}", schema, table));
+    let mut writer = create_data_writer(eargs, sink)?;
+    let count = export_native::export_native_rows(progress_fun, runtime, client, schema, table, predicate, ntf, &mut writer)?;
+    let (_, sha256) = writer.finish()?;
+    progress_fun(&format!("{} rows copied.", count));
+    Ok((count as i64, Some(sha256)))
+}
+
+fn stream_text_data<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, eargs: &ExportArgs, schema: &str, table: &str,
+                    predicate: &str, ntf: &export_native::NativeTableFormat, tf: &TextFormat, sink: &mut DataSink) -> Result<(i64, Option<String>), TransferError> {
+    progress_fun(&format!("Exporting data as text: {}.{}", schema, table));
+    let mut writer = create_data_writer(eargs, sink)?;
+    let count = export_text::export_text_rows(progress_fun, runtime, client, schema, table, predicate, ntf, tf, &mut writer)?;
+    let (_, sha256) = writer.finish()?;
+    progress_fun(&format!("{} rows copied.", count));
+    Ok((count as i64, Some(sha256)))
+}
+
+fn stream_sql_data<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, eargs: &ExportArgs, schema: &str, table: &str,
+                   predicate: &str, stf: &export_sql::SqlTableFormat, sf: &SqlFormat, sink: &mut DataSink) -> Result<(i64, Option<String>), TransferError> {
+    progress_fun(&format!("Exporting data as SQL script: {}.{}", schema, table));
+    let mut writer = create_data_writer(eargs, sink)?;
+    let count = export_sql::export_sql_rows(progress_fun, runtime, client, schema, table, predicate, stf, sf, &mut writer)?;
+    let (_, sha256) = writer.finish()?;
+    progress_fun(&format!("{} rows copied.", count));
+    Ok((count as i64, Some(sha256)))
+}
+
+// checksum of the Parquet file is taken from the written data as is
+fn write_parquet_data<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, eargs: &ExportArgs, schema: &str, table: &str,
+                      predicate: &str, ntf: &export_native::NativeTableFormat, pf: &ParquetFormat, sink: &mut DataSink) -> Result<(i64, Option<String>), TransferError> {
+    progress_fun(&format!("Exporting data as Parquet: {}.{}", schema, table));
+    let count = export_parquet::export_parquet_rows(progress_fun, runtime, client, schema, table, predicate, ntf, pf,
+                                                    eargs.codec, eargs.compression_level, &mut *sink)?;
+    progress_fun(&format!("{} rows copied.", count));
+    Ok((count as i64, None))
 }
 
-fn export_tables<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, eargs: &ExportArgs, dest_dir: &str) -> Result<(), TransferError> {
-    for table in eargs.tables.iter() {
-        let format_filename = run_bcp_format(progress_fun, cc, dest_dir, &eargs.dbname, &table.schema, &table.table)?;
-        let data_filename = run_bcp_data(progress_fun, cc, dest_dir, &eargs.dbname, &table.schema, &table.table, &format_filename)?;
-        let _ = compress_bcp_file(progress_fun, &dest_dir, &data_filename)?;
+fn bcp_data_query(dbname: &str, table: &TableWithRowsCount, predicate: &str, columns: &Vec<String>) -> String {
+    if predicate.is_empty() && columns.is_empty() {
+        return String::new();
     }
-    Ok(())
+    let select_list = if columns.is_empty() {
+        "*".to_string()
+    } else {
+        columns.iter().map(|c| quote_ident(c)).collect::<Vec<String>>().join(", ")
+    };
+    let mut query = format!("select {} from [{}].[{}].[{}]", select_list, dbname, &table.schema, &table.table);
+    if !predicate.is_empty() {
+        query.push_str(&format!(" where {}", predicate));
+    }
+    query
+}
+
+fn select_format_columns(work_dir: &str, format_filename: &str, selection: &ColumnSelection) -> Result<Vec<String>, TransferError> {
+    let format_path = Path::new(work_dir).join(format_filename);
+    let mut format = BcpFormat::read_file(&format_path)?;
+    let indices = selection.select(&format)?;
+    format.retain_columns(&indices);
+    format.write_file(&format_path)?;
+    Ok(format.columns.iter().map(|c| c.name.clone()).collect())
 }
 
-fn zip_dest_directory<P: Fn(&str)->()>(progress_fun: &P, dest_dir: &str, filename: &str) -> Result<(), TransferError> {
-    let dest_dir_path = Path::new(dest_dir);
-    let parent_path = match dest_dir_path.parent() {
-        Some(path) => path,
-        None => return Err(TransferError::from_str(
-            "Error accessing destination directory parent"))
+fn record_table_files<P: Fn(&str)->()>(progress_fun: &P, export_file: &mut ExportFile, schema: &str, table: &str, part: &str,
+                      files: Vec<(String, Vec<u8>)>, data_file: Option<JournalFile>) -> Result<(), TransferError> {
+    let mut journal_files = Vec::new();
+    for (filename, bytes) in files {
+        progress_fun(&format!("Adding to export file: {}", filename));
+        let (size, sha256) = export_file.archive.add_bytes(&filename, &bytes)?;
+        journal_files.push(JournalFile {
+            filename,
+            size,
+            sha256,
+            row_count: -1
+        });
+    }
+    journal_files.extend(data_file);
+    export_file.journal.record(schema, table, part, journal_files)
+}
+
+// files are produced in memory and are added to the export file as a whole
+fn archive_table_files<P: Fn(&str)->()>(progress_fun: &P, export_file: &Mutex<ExportFile>, schema: &str, table: &str,
+                       part: &str, files: Vec<(String, Vec<u8>)>) -> Result<(), TransferError> {
+    let mut guard = match export_file.lock() {
+        Ok(guard) => guard,
+        Err(_) => return Err(TransferError::from_str("Error accessing export file"))
     };
-    let dest_dir_st = match dest_dir_path.to_str() {
-        Some(st) => st,
-        None => return Err(TransferError::from_str(
-            "Error accessing destination directory"))
+    record_table_files(progress_fun, &mut guard, schema, table, part, files, None)
+}
+
+// data is written without holding the export file, so the workers run in parallel,
+// the export file is locked only to copy the spooled data into the entry
+fn archive_table_data<P: Fn(&str)->(), F>(progress_fun: &P, export_file: &Mutex<ExportFile>, schema: &str, table: &str, part: &str,
+                      files: Vec<(String, Vec<u8>)>, data_filename: &str, write_data: F) -> Result<(), TransferError>
+where F: FnOnce(&mut DataSink) -> Result<(i64, Option<String>), TransferError> {
+    let sink = match export_file.lock() {
+        Ok(guard) => if guard.data_dir.is_empty() {
+            progress_fun(&format!("Spooling data: {}", data_filename));
+            DataSink::Spool(guard.archive.create_spool(data_filename)?)
+        } else {
+            progress_fun(&format!("Writing to data directory: {}", data_filename));
+            let path = Path::new(&guard.data_dir).join(data_filename);
+            let writer = BufWriter::new(File::create(&path)?);
+            DataSink::File(path, writer, Sha256::new())
+        },
+        Err(_) => return Err(TransferError::from_str("Error accessing export file"))
     };
-    let dest_file_buf = parent_path.join(filename);
-    let dest_file_st = match dest_file_buf.to_str() {
-        Some(st) => st,
-        None => return Err(TransferError::from_str(
-            "Error accessing destination file"))
+    let spool_path = match &sink {
+        DataSink::Spool(writer) => Some(writer.path().to_path_buf()),
+        DataSink::File(..) => None
     };
-    let listener = |en: &str| {
-        progress_fun(en);
+    let mut data_file = write_data_sink(sink, data_filename, write_data)?;
+    let mut guard = match export_file.lock() {
+        Ok(guard) => guard,
+        Err(_) => {
+            if let Some(path) = &spool_path {
+                let _ = fs::remove_file(path);
+            }
+            return Err(TransferError::from_str("Error accessing export file"))
+        }
     };
-    zip_recurse::zip_directory_listen(dest_dir_st, dest_file_st, 0, listener)?;
-    std::fs::remove_dir_all(dest_dir_path)?;
-    Ok(())
+    if let Some(path) = &spool_path {
+        progress_fun(&format!("Adding to export file: {}", data_filename));
+        let res = guard.archive.add_spool(data_filename, path);
+        let _ = fs::remove_file(path);
+        data_file.size = res?;
+    }
+    record_table_files(progress_fun, &mut guard, schema, table, part, files, Some(data_file))
+}
+
+enum TableFormat {
+    Native(export_native::NativeTableFormat),
+    Bcp(Vec<String>),
+    Text(export_native::NativeTableFormat),
+    Parquet(export_native::NativeTableFormat),
+    Sql(export_sql::SqlTableFormat),
+}
+
+// bcp.exe writes the format file into the work directory, it is read back when archived
+// and is removed after the data is exported
+fn export_format<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, eargs: &ExportArgs, work_dir: &str, table: &TableWithRowsCount,
+                 format_filename: &str, conn: &mut Option<(Runtime, Client<Compat<TcpStream>>)>) -> Result<TableFormat, TransferError> {
+    let selection = eargs.columns.iter().find(|cs| cs.matches(&table.schema, &table.table));
+    if let DataFormat::Text(_) = &eargs.data_format {
+        // format file is not written for text data
+        let (runtime, client) = cc.open_cached_connection(&eargs.dbname, conn)?;
+        let ntf = export_text::select_text_format(runtime, client, &table.schema, &table.table, selection)?;
+        Ok(TableFormat::Text(ntf))
+    } else if let DataFormat::Parquet(_) = &eargs.data_format {
+        // column types are stored in Parquet schema, char data is read as unicode strings
+        let (runtime, client) = cc.open_cached_connection(&eargs.dbname, conn)?;
+        let ntf = export_text::select_text_format(runtime, client, &table.schema, &table.table, selection)?;
+        Ok(TableFormat::Parquet(ntf))
+    } else if let DataFormat::Sql(_) = &eargs.data_format {
+        let (runtime, client) = cc.open_cached_connection(&eargs.dbname, conn)?;
+        let stf = export_sql::select_sql_format(runtime, client, &table.schema, &table.table, selection)?;
+        Ok(TableFormat::Sql(stf))
+    } else if eargs.native_tds {
+        let (runtime, client) = cc.open_cached_connection(&eargs.dbname, conn)?;
+        let ntf = export_native::run_native_format(progress_fun, runtime, client, &table.schema, &table.table, selection)?;
+        Ok(TableFormat::Native(ntf))
+    } else {
+        run_bcp_format(progress_fun, cc, work_dir, &eargs.dbname, &table.schema, &table.table, format_filename)?;
+        let columns = match selection {
+            Some(sel) => select_format_columns(work_dir, format_filename, sel)?,
+            None => Vec::new()
+        };
+        Ok(TableFormat::Bcp(columns))
+    }
+}
+
+fn format_file_bytes(work_dir: &str, format_filename: &str, format: &TableFormat) -> Result<Vec<u8>, TransferError> {
+    match format {
+        TableFormat::Native(ntf) => Ok(ntf.format.to_xml().into_bytes()),
+        TableFormat::Bcp(_) => Ok(fs::read(Path::new(work_dir).join(format_filename))?),
+        _ => Err(TransferError::from_str("Format file is only written for bcp data"))
+    }
+}
+
+fn remove_format_file(work_dir: &str, format_filename: &str, format: &TableFormat) {
+    if let TableFormat::Bcp(_) = format {
+        let _ = fs::remove_file(Path::new(work_dir).join(format_filename));
+    }
+}
+
+// without streaming, bcp.exe writes the data file into the work directory before
+// the export file is locked, the file is compressed into the entry and removed,
+// returns the number of rows copied, or -1 when the data is exported otherwise
+fn run_bcp_file<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, eargs: &ExportArgs, work_dir: &str, table: &TableWithRowsCount, predicate: &str,
+                format_filename: &str, format: &TableFormat, data_filename: &str) -> Result<i64, TransferError> {
+    if let TableFormat::Bcp(columns) = format {
+        if !eargs.stream_compression {
+            let query = bcp_data_query(&eargs.dbname, table, predicate, columns);
+            return run_bcp_data(progress_fun, cc, work_dir, &eargs.dbname, &table.schema, &table.table, &query, format_filename, data_filename);
+        }
+    }
+    Ok(-1)
 }
 
-fn prepare_dest_dir(dest_parent_dir: &str, dest_filename: &str) -> Result<(String, String), TransferError> {
+fn export_data<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, eargs: &ExportArgs, work_dir: &str, table: &TableWithRowsCount, predicate: &str,
+               format_filename: &str, format: &TableFormat, data_filename: &str, copied_rows: i64, conn: &mut Option<(Runtime, Client<Compat<TcpStream>>)>,
+               sink: &mut DataSink) -> Result<(i64, Option<String>), TransferError> {
+    match format {
+        TableFormat::Native(ntf) => {
+            let (runtime, client) = cc.open_cached_connection(&eargs.dbname, conn)?;
+            stream_native_data(progress_fun, runtime, client, eargs, &table.schema, &table.table, predicate, ntf, sink)
+        },
+        TableFormat::Bcp(columns) => {
+            let query = bcp_data_query(&eargs.dbname, table, predicate, columns);
+            if eargs.stream_compression {
+                stream_bcp_data(progress_fun, cc, eargs, work_dir, &table.schema, &table.table, &query, format_filename, sink)
+            } else {
+                compress_bcp_file(progress_fun, eargs, work_dir, data_filename, copied_rows, sink)
+            }
+        },
+        TableFormat::Text(ntf) => {
+            let tf = match &eargs.data_format {
+                DataFormat::Text(tf) => tf,
+                _ => return Err(TransferError::from_str("Text format options not specified"))
+            };
+            let (runtime, client) = cc.open_cached_connection(&eargs.dbname, conn)?;
+            stream_text_data(progress_fun, runtime, client, eargs, &table.schema, &table.table, predicate, ntf, tf, sink)
+        },
+        TableFormat::Parquet(ntf) => {
+            let pf = match &eargs.data_format {
+                DataFormat::Parquet(pf) => pf,
+                _ => return Err(TransferError::from_str("Parquet format options not specified"))
+            };
+            let (runtime, client) = cc.open_cached_connection(&eargs.dbname, conn)?;
+            write_parquet_data(progress_fun, runtime, client, eargs, &table.schema, &table.table, predicate, ntf, pf, sink)
+        },
+        TableFormat::Sql(stf) => {
+            let sf = match &eargs.data_format {
+                DataFormat::Sql(sf) => sf,
+                _ => return Err(TransferError::from_str("SQL format options not specified"))
+            };
+            let (runtime, client) = cc.open_cached_connection(&eargs.dbname, conn)?;
+            stream_sql_data(progress_fun, runtime, client, eargs, &table.schema, &table.table, predicate, stf, sf, sink)
+        }
+    }
+}
+
+// returns the parts of the table data that are to be exported separately
+fn export_table<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, eargs: &ExportArgs, work_dir: &str, table: &TableWithRowsCount,
+                export_file: &Mutex<ExportFile>, conn: &mut Option<(Runtime, Client<Compat<TcpStream>>)>) -> Result<Vec<TablePart>, TransferError> {
+    let (runtime, client) = cc.open_cached_connection(&eargs.dbname, conn)?;
+    let (ddl_filename, ddl) = run_table_ddl(progress_fun, runtime, client, &table.schema, &table.table)?;
+    let mut files = vec!((ddl_filename, ddl.into_bytes()));
+    if let Some((post_filename, post)) = run_post_data(progress_fun, runtime, client, &table.schema, &table.table)? {
+        files.push((post_filename, post.into_bytes()));
+    }
+    if eargs.schema_only {
+        archive_table_files(progress_fun, export_file, &table.schema, &table.table, "", files)?;
+        return Ok(Vec::new());
+    }
+    let parts = if eargs.chunk_rows > 0 && table.row_count > eargs.chunk_rows {
+        plan_table_parts(progress_fun, runtime, client, &table.schema, &table.table, table.row_count, eargs.chunk_rows)?
+    } else {
+        Vec::new()
+    };
+    let format_filename = format!("{}.{}.xml", &table.schema, &table.table);
+    let format = export_format(progress_fun, cc, eargs, work_dir, table, &format_filename, conn)?;
+    let res = export_table_files(progress_fun, cc, eargs, work_dir, table, export_file, conn, files, &parts, &format_filename, &format);
+    remove_format_file(work_dir, &format_filename, &format);
+    res?;
+    Ok(parts)
+}
+
+fn export_table_files<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, eargs: &ExportArgs, work_dir: &str, table: &TableWithRowsCount,
+                      export_file: &Mutex<ExportFile>, conn: &mut Option<(Runtime, Client<Compat<TcpStream>>)>, mut files: Vec<(String, Vec<u8>)>,
+                      parts: &Vec<TablePart>, format_filename: &str, format: &TableFormat) -> Result<(), TransferError> {
+    if DataFormat::Bcp == eargs.data_format {
+        files.push((format_filename.to_string(), format_file_bytes(work_dir, format_filename, format)?));
+    }
+    if !parts.is_empty() {
+        // part key ranges are kept to resume the export and to clean up a part on import
+        let parts_filename = format!("{}.{}.parts.txt", &table.schema, &table.table);
+        files.push((parts_filename, parts_to_text(parts).into_bytes()));
+    }
+    if !table.predicate.is_empty() {
+        // record the filter, so it is known that the data is partial
+        let filter_filename = format!("{}.{}.filter.sql", &table.schema, &table.table);
+        files.push((filter_filename, table.predicate.clone().into_bytes()));
+    }
+    if !parts.is_empty() {
+        return archive_table_files(progress_fun, export_file, &table.schema, &table.table, "", files);
+    }
+    let data_filename = format!("{}.{}{}", &table.schema, &table.table, eargs.data_format.extension());
+    let copied_rows = run_bcp_file(progress_fun, cc, eargs, work_dir, table, &table.predicate, format_filename, format, &data_filename)?;
+    archive_table_data(progress_fun, export_file, &table.schema, &table.table, "", files, &data_entry_name(eargs, &data_filename), |sink| {
+        export_data(progress_fun, cc, eargs, work_dir, table, &table.predicate, format_filename, format, &data_filename, copied_rows, conn, sink)
+    })
+}
+
+fn export_table_part<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, eargs: &ExportArgs, work_dir: &str, table: &TableWithRowsCount, part: &TablePart,
+                     export_file: &Mutex<ExportFile>, conn: &mut Option<(Runtime, Client<Compat<TcpStream>>)>) -> Result<(), TransferError> {
+    // format file is created again for each part, but only the data is archived
+    let format_filename = format!("{}.{}.{}.xml", &table.schema, &table.table, &part.name);
+    let format = export_format(progress_fun, cc, eargs, work_dir, table, &format_filename, conn)?;
+    let data_filename = format!("{}.{}.{}{}", &table.schema, &table.table, &part.name, eargs.data_format.extension());
+    let predicate = combine_predicates(&table.predicate, &part.predicate);
+    let res = run_bcp_file(progress_fun, cc, eargs, work_dir, table, &predicate, &format_filename, &format, &data_filename)
+        .and_then(|copied_rows| archive_table_data(progress_fun, export_file, &table.schema, &table.table, &part.name, Vec::new(),
+                                                   &data_entry_name(eargs, &data_filename), |sink| {
+            export_data(progress_fun, cc, eargs, work_dir, table, &predicate, &format_filename, &format, &data_filename, copied_rows, conn, sink)
+        }));
+    remove_format_file(work_dir, &format_filename, &format);
+    res
+}
+
+// script refers to the data files by their uncompressed names,
+// it is also written next to the data files when the data directory is used
+fn write_sql_load_script<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, eargs: &ExportArgs,
+                         export_file: &Mutex<ExportFile>, tables: &Vec<(String, String)>) -> Result<String, TransferError> {
+    let data_files: Vec<Vec<String>> = match export_file.lock() {
+        Ok(guard) => tables.iter().map(|(schema, table)| {
+            guard.journal.table_records(schema, table).iter()
+                .flat_map(|(_, files)| files.iter())
+                .filter(|jf| is_data_file(&jf.filename))
+                .map(|jf| jf.filename.strip_suffix(eargs.codec.suffix()).unwrap_or(&jf.filename).to_string())
+                .collect()
+        }).collect(),
+        Err(_) => return Err(TransferError::from_str("Error accessing export file"))
+    };
+    let script = export_sql::write_load_script(progress_fun, cc, &eargs.dbname, tables, &data_files)?;
+    if !eargs.data_dir.is_empty() {
+        fs::write(Path::new(&eargs.data_dir).join(export_sql::LOAD_SCRIPT_FILENAME), &script)?;
+    }
+    if CompressionCodec::Uncompressed != eargs.codec {
+        progress_fun("Data files are compressed, they need to be decompressed before running the load script");
+    }
+    Ok(script)
+}
+
+// database level entries are recorded in journal with empty schema and table names
+fn export_database_files<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, eargs: &ExportArgs,
+                         export_file: &Mutex<ExportFile>) -> Result<(), TransferError> {
+    let done = match export_file.lock() {
+        Ok(guard) => guard.journal.table_files("", "", "").is_some(),
+        Err(_) => return Err(TransferError::from_str("Error accessing export file"))
+    };
+    if done {
+        progress_fun("Already exported: database objects");
+        return Ok(());
+    }
+    let runtime = cc.create_runtime()?;
+    let mut client = cc.open_connection_to_db(&runtime, &eargs.dbname)?;
+    let mut files = Vec::new();
+    if eargs.export_modules {
+        let (modules_filename, modules) = run_sql_modules(progress_fun, &runtime, &mut client)?;
+        files.push((modules_filename, modules.into_bytes()));
+    }
+    if eargs.export_security {
+        let (security_filename, security) = run_security(progress_fun, &runtime, &mut client)?;
+        files.push((security_filename, security.into_bytes()));
+    }
+    // identity values are read after the data is exported to cover rows inserted meanwhile
+    let tables: Vec<(String, String)> = eargs.tables.iter()
+        .map(|t| (t.schema.clone(), t.table.clone()))
+        .collect();
+    let (reseed_filename, reseed) = run_reseed(progress_fun, &runtime, &mut client, &tables)?;
+    files.push((reseed_filename, reseed.into_bytes()));
+    if let DataFormat::Sql(_) = &eargs.data_format {
+        if !eargs.schema_only {
+            let script = write_sql_load_script(progress_fun, cc, eargs, export_file, &tables)?;
+            files.push((export_sql::LOAD_SCRIPT_FILENAME.to_string(), script.into_bytes()));
+        }
+    }
+    archive_table_files(progress_fun, export_file, "", "", "", files)
+}
+
+// waits while other workers are running, as they can add table parts to the queue
+fn next_export_task(schedule: &Mutex<ExportSchedule>, cvar: &Condvar) -> Option<ExportTask> {
+    let mut st = match schedule.lock() {
+        Ok(st) => st,
+        Err(_) => return None
+    };
+    loop {
+        if st.failed {
+            return None;
+        }
+        if let Some(task) = st.pending.pop_front() {
+            st.running += 1;
+            return Some(task);
+        }
+        if 0 == st.running {
+            return None;
+        }
+        st = match cvar.wait(st) {
+            Ok(st) => st,
+            Err(_) => return None
+        };
+    }
+}
+
+fn manifest_options(eargs: &ExportArgs) -> ManifestOptions {
+    ManifestOptions {
+        native_tds: eargs.native_tds,
+        stream_compression: eargs.stream_compression,
+        schema_only: eargs.schema_only,
+        modules: eargs.export_modules,
+        security: eargs.export_security,
+        chunk_rows: eargs.chunk_rows,
+        codec: eargs.codec.label().to_string(),
+        compression_level: eargs.compression_level,
+        encrypted: eargs.encryption.is_enabled(),
+        data_format: eargs.data_format.label().to_string(),
+        text_format: match &eargs.data_format {
+            DataFormat::Text(tf) => format!("delimiter: {:?}, quoting: {}, header: {}, encoding: {}, null: {:?}",
+                                            tf.delimiter, tf.quoting.label(), tf.header, tf.encoding.label(), &tf.null_value),
+            DataFormat::Parquet(pf) => format!("row group rows: {}", pf.row_group_rows),
+            DataFormat::Sql(sf) => format!("batch rows: {}", sf.batch_rows),
+            DataFormat::Bcp => String::new()
+        },
+        external_data: !eargs.data_dir.is_empty(),
+        filtered_tables: eargs.tables.iter()
+            .filter(|t| !t.predicate.is_empty())
+            .map(|t| format!("{}.{}: {}", &t.schema, &t.table, &t.predicate))
+            .collect(),
+        column_selections: eargs.columns.iter()
+            .map(|cs| {
+                let columns: Vec<String> = cs.include.iter().cloned()
+                    .chain(cs.exclude.iter().map(|c| format!("-{}", c)))
+                    .collect();
+                format!("{}.{}: {}", &cs.schema, &cs.table, columns.join(", "))
+            })
+            .collect()
+    }
+}
+
+// manifest is written last, sizes of the table entries are taken from the journal,
+// it is recorded in journal as a database level entry with 'manifest' part name
+// together with its signature
+fn export_manifest<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, eargs: &ExportArgs,
+                   export_file: &Mutex<ExportFile>, signing_key: Option<&SigningKey>) -> Result<(), TransferError> {
+    let (tables, entries) = match export_file.lock() {
+        Ok(guard) => {
+            if guard.journal.table_files("", "", "manifest").is_some() {
+                progress_fun("Already exported: manifest");
+                return Ok(());
+            }
+            let tables = eargs.tables.iter().map(|t| {
+                let records = guard.journal.table_records(&t.schema, &t.table);
+                let data_files: Vec<&JournalFile> = records.iter()
+                    .flat_map(|(_, files)| files.iter())
+                    .filter(|jf| is_data_file(&jf.filename))
+                    .collect();
+                // rows written into the data files, unknown if not reported for any of them
+                let row_count = if data_files.iter().any(|jf| jf.row_count < 0) {
+                    -1
+                } else {
+                    data_files.iter().map(|jf| jf.row_count).sum()
+                };
+                ManifestTable {
+                    schema: t.schema.clone(),
+                    table: t.table.clone(),
+                    row_count,
+                    size_bytes: data_files.iter().map(|jf| jf.size).sum(),
+                    parts: records.iter()
+                        .filter(|(part, _)| !part.is_empty())
+                        .map(|(part, _)| part.to_string())
+                        .collect(),
+                    data_files: data_files.iter().map(|jf| jf.filename.clone()).collect()
+                }
+            }).collect();
+            let mut entries: Vec<ManifestEntry> = guard.journal.all_files()
+                .map(|jf| ManifestEntry {
+                    name: jf.filename.clone(),
+                    size: jf.size,
+                    sha256: jf.sha256.clone(),
+                    external: !guard.data_dir.is_empty() && is_data_file(&jf.filename)
+                })
+                .collect();
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+            (tables, entries)
+        },
+        Err(_) => return Err(TransferError::from_str("Error accessing export file"))
+    };
+    progress_fun("Writing manifest ...");
+    let runtime = cc.create_runtime()?;
+    let mut client = cc.open_connection_to_db(&runtime, &eargs.dbname)?;
+    let server = if cc.use_named_instance {
+        format!("{}\\{}", &cc.hostname, &cc.instance)
+    } else {
+        format!("{},{}", &cc.hostname, &cc.port)
+    };
+    let manifest = Manifest {
+        tool_version: labels::VERSION.to_string(),
+        format_version: MANIFEST_FORMAT_VERSION,
+        source: ManifestSource {
+            server,
+            server_version: load_server_version(&runtime, &mut client)?,
+            database: eargs.dbname.clone()
+        },
+        exported_at: chrono::Local::now().to_rfc3339(),
+        options: manifest_options(eargs),
+        tables,
+        entries
+    };
+    let manifest_text = manifest.to_json()?;
+    let mut files = Vec::new();
+    if let Some(key) = signing_key {
+        progress_fun("Signing manifest ...");
+        files.push((SIGNATURE_FILENAME.to_string(), sign_manifest(key, &manifest_text).into_bytes()));
+    }
+    files.insert(0, (MANIFEST_FILENAME.to_string(), manifest_text.into_bytes()));
+    archive_table_files(progress_fun, export_file, "", "", "manifest", files)
+}
+
+fn export_worker(cc: &TdsConnConfig, eargs: &ExportArgs, work_dir: &str, export_file: &Mutex<ExportFile>, schedule: &Mutex<ExportSchedule>,
+                 cvar: &Condvar, results: &Mutex<Vec<(usize, String, String, Result<(), TransferError>)>>, sender: mpsc::Sender<String>) {
+    let mut conn = None;
+    while let Some(task) = next_export_task(schedule, cvar) {
+        let table = &eargs.tables[task.table_idx];
+        let tag = match &task.part {
+            Some(part) => format!("{}.{} {}", &table.schema, &table.table, &part.name),
+            None => format!("{}.{}", &table.schema, &table.table)
+        };
+        let progress_fun = |st: &str| {
+            // empty message is used to flush progress output
+            let msg = if st.is_empty() { String::new() } else { format!("[{}] {}", tag, st) };
+            let _ = sender.send(msg);
+        };
+        let res = match &task.part {
+            Some(part) => export_table_part(&progress_fun, cc, eargs, work_dir, table, part, export_file, &mut conn).map(|_| Vec::new()),
+            None => export_table(&progress_fun, cc, eargs, work_dir, table, export_file, &mut conn)
+        };
+        if let Ok(mut st) = schedule.lock() {
+            st.running -= 1;
+            match &res {
+                // parts go first, so they are spread over all workers
+                Ok(parts) => for part in parts.iter().rev() {
+                    st.pending.push_front(ExportTask {
+                        table_idx: task.table_idx,
+                        part: Some(part.clone())
+                    });
+                },
+                Err(_) => st.failed = true
+            }
+        }
+        cvar.notify_all();
+        if let Ok(mut results) = results.lock() {
+            let part_name = task.part.as_ref().map(|part| part.name.clone()).unwrap_or_default();
+            results.push((task.table_idx, part_name, tag.clone(), res.map(|_| ())));
+        }
+    }
+}
+
+fn export_tables<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, eargs: &ExportArgs, work_dir: &str,
+                 export_file: &Mutex<ExportFile>, completed: &Vec<bool>, resumed_parts: Vec<ExportTask>) -> Result<(), TransferError> {
+    // largest tables first, so they do not end up running alone at the end
+    let mut order: Vec<usize> = (0..eargs.tables.len()).filter(|idx| !completed[*idx]).collect();
+    order.sort_by(|a, b| eargs.tables[*b].row_count.cmp(&eargs.tables[*a].row_count));
+    let count = order.len();
+    let mut pending: VecDeque<ExportTask> = resumed_parts.into_iter().collect();
+    pending.extend(order.into_iter().map(|idx| ExportTask {
+        table_idx: idx,
+        part: None
+    }));
+    let schedule = Mutex::new(ExportSchedule {
+        pending,
+        ..Default::default()
+    });
+    let cvar = Condvar::new();
+    let results: Mutex<Vec<(usize, String, String, Result<(), TransferError>)>> = Mutex::new(Vec::new());
+    let jobs = eargs.jobs.max(1);
+    progress_fun(&format!("Exporting {} tables using {} worker(s)", count, jobs));
+
+    let (sender, receiver) = mpsc::channel::<String>();
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let schedule = &schedule;
+            let cvar = &cvar;
+            let results = &results;
+            scope.spawn(move || {
+                export_worker(cc, eargs, work_dir, export_file, schedule, cvar, results, sender);
+            });
+        }
+        std::mem::drop(sender);
+        for msg in receiver.iter() {
+            progress_fun(&msg);
+        }
+    });
+
+    let mut results = match results.into_inner() {
+        Ok(results) => results,
+        Err(_) => return Err(TransferError::from_str("Export worker failure"))
+    };
+    // reported in table order, table parts go after the table sorted by name
+    results.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+    for (idx, table) in eargs.tables.iter().enumerate() {
+        if completed[idx] {
+            progress_fun(&format!("Already exported: {}.{}", &table.schema, &table.table));
+        } else if !results.iter().any(|(ridx, _, _, _)| *ridx == idx) {
+            progress_fun(&format!("Skipped: {}.{}", &table.schema, &table.table));
+        }
+    }
+    let mut first_error: Option<TransferError> = None;
+    for (_, _, tag, res) in results.into_iter() {
+        match res {
+            Ok(()) => progress_fun(&format!("Exported: {}", tag)),
+            Err(e) => {
+                progress_fun(&format!("Failed: {}, error: {}", tag, e));
+                if first_error.is_none() {
+                    first_error = Some(TransferError::from_string(format!(
+                        "Error exporting table: {}, message: {}", tag, e)));
+                }
+            }
+        }
+    }
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(())
+    }
+}
+
+// returns the name of the directory inside the export file and the export file name
+fn export_file_names(dest_filename: &str) -> (String, String) {
     let mut ext = Path::new(dest_filename).extension().unwrap_or(OsStr::new(""))
         .to_str().unwrap_or("").to_string();
     let mut filename = dest_filename.to_string();
@@ -298,8 +1084,16 @@ fn prepare_dest_dir(dest_parent_dir: &str, dest_filename: &str) -> Result<(Strin
         filename = format!("{}.{}", filename, ext);
     }
     let dirname: String = filename.chars().take(filename.len() - (ext.len() + 1)).collect();
-    let parent_dir_path = Path::new(dest_parent_dir);
-    let dir_path = parent_dir_path.join(dirname);
+    (dirname, filename)
+}
+
+// bcp.exe reads and writes format and data files on disk, other exports
+// are streamed into the export file and do not need a work directory
+fn prepare_work_dir(eargs: &ExportArgs, dirname: &str) -> Result<String, TransferError> {
+    if eargs.native_tds || DataFormat::Bcp != eargs.data_format || eargs.schema_only {
+        return Ok(String::new());
+    }
+    let dir_path = Path::new(&eargs.parent_dir).join(dirname);
     let dir_path_st = match dir_path.to_str() {
         Some(st) => st.to_string(),
         None => return Err(TransferError::from_str("Error reading directory name"))
@@ -310,32 +1104,209 @@ fn prepare_dest_dir(dest_parent_dir: &str, dest_filename: &str) -> Result<(Strin
             "Error removing directory: {}", dir_path_st)))
     }
     fs::create_dir_all(dir_path)?;
-    Ok((dir_path_st, filename))
+    Ok(dir_path_st)
+}
+
+fn remove_work_dir(work_dir: &str) {
+    if !work_dir.is_empty() {
+        let _ = fs::remove_dir_all(work_dir);
+    }
+}
+
+// parts of the completed tables that are not yet exported
+fn resumed_parts(journal: &ExportJournal, eargs: &ExportArgs, dest_file_path: &Path, dirname: &str,
+                 completed: &Vec<bool>, key: Option<&EncryptionKey>) -> Result<Vec<ExportTask>, TransferError> {
+    let mut tasks = Vec::new();
+    for (idx, table) in eargs.tables.iter().enumerate() {
+        let parts_filename = format!("{}.{}.parts.txt", &table.schema, &table.table);
+        let has_parts = match journal.table_files(&table.schema, &table.table, "") {
+            Some(files) => completed[idx] && files.iter().any(|jf| jf.filename == parts_filename),
+            None => false
+        };
+        if !has_parts {
+            continue;
+        }
+        let text = ArchiveWriter::read_part_text(dest_file_path, dirname, &parts_filename, key)?;
+        for part in parse_parts(&text)? {
+            if journal.table_files(&table.schema, &table.table, &part.name).is_none() {
+                tasks.push(ExportTask {
+                    table_idx: idx,
+                    part: Some(part)
+                });
+            }
+        }
+    }
+    Ok(tasks)
+}
+
+// encryption info is stored unencrypted and recorded in journal
+// as a database level entry with 'encryption' part name
+fn add_encryption_entry<P: Fn(&str)->()>(progress_fun: &P, eargs: &ExportArgs,
+                        export_file: &mut ExportFile) -> Result<(), TransferError> {
+    progress_fun("Deriving encryption key ...");
+    let (info, key) = EncryptionInfo::generate(&eargs.encryption)?;
+    let (size, sha256) = export_file.archive.add_bytes(ENCRYPTION_FILENAME, info.to_json()?.as_bytes())?;
+    export_file.journal.record("", "", "encryption", vec!(JournalFile {
+        filename: ENCRYPTION_FILENAME.to_string(),
+        size,
+        sha256,
+        row_count: -1
+    }))?;
+    export_file.archive.set_key(key);
+    Ok(())
+}
+
+fn resumed_key(journal: &ExportJournal, eargs: &ExportArgs, dest_file_path: &Path,
+               dirname: &str) -> Result<Option<EncryptionKey>, TransferError> {
+    let encrypted = journal.table_files("", "", "encryption").is_some();
+    if encrypted != eargs.encryption.is_enabled() {
+        return Err(TransferError::from_str(
+            "Encryption options do not match the previous export, run the export without 'resume' option"));
+    }
+    if !encrypted {
+        return Ok(None);
+    }
+    let text = ArchiveWriter::read_part_text(dest_file_path, dirname, ENCRYPTION_FILENAME, None)?;
+    Ok(Some(EncryptionInfo::parse(&text)?.derive_key(&eargs.encryption)?))
+}
+
+fn open_export_file<P: Fn(&str)->()>(progress_fun: &P, eargs: &ExportArgs, dest_file_path: &Path, dirname: &str,
+                    journal_path: &Path) -> Result<(ExportFile, Vec<bool>, Vec<ExportTask>), TransferError> {
+    if eargs.resume {
+        if journal_path.exists() && ArchiveWriter::part_path(dest_file_path).exists() {
+            progress_fun(&format!("Resuming export using journal: {}", journal_path.to_string_lossy()));
+            let journal = ExportJournal::open(journal_path, &eargs.dbname)?;
+            let completed: Vec<bool> = eargs.tables.iter()
+                .map(|t| journal.table_files(&t.schema, &t.table, "").is_some())
+                .collect();
+            let key = resumed_key(&journal, eargs, dest_file_path, dirname)?;
+            let parts = resumed_parts(&journal, eargs, dest_file_path, dirname, &completed, key.as_ref())?;
+            progress_fun("Checking entries recorded in journal ...");
+            let checksums = ArchiveWriter::part_sha256(dest_file_path, dirname, key.as_ref())?;
+            let (mut archive, sizes) = ArchiveWriter::reopen(dest_file_path, dirname)?;
+            let mut journaled = HashSet::new();
+            for jf in journal.all_files() {
+                if !eargs.data_dir.is_empty() && is_data_file(&jf.filename) {
+                    let path = Path::new(&eargs.data_dir).join(&jf.filename);
+                    let size = fs::metadata(&path).map(|md| md.len()).ok();
+                    let matches = size == Some(jf.size) &&
+                        entry_sha256(File::open(&path)?, data_file_codec(&jf.filename))? == jf.sha256;
+                    if !matches {
+                        return Err(TransferError::from_string(format!(
+                            "Data directory does not match the journal, file: {}, run the export without 'resume' option", &jf.filename)));
+                    }
+                    continue;
+                }
+                if sizes.get(&jf.filename) != Some(&jf.size) || checksums.get(&jf.filename) != Some(&jf.sha256) {
+                    return Err(TransferError::from_string(format!(
+                        "Export file does not match the journal, entry: {}, run the export without 'resume' option", &jf.filename)));
+                }
+                journaled.insert(jf.filename.clone());
+            }
+            // entries not recorded in journal would be duplicated when re-exported
+            for filename in sizes.keys() {
+                if !journaled.contains(filename) {
+                    return Err(TransferError::from_string(format!(
+                        "Export file contains an entry not recorded in the journal: {}, run the export without 'resume' option", filename)));
+                }
+            }
+            if let Some(key) = key {
+                archive.set_key(key);
+            }
+            return Ok((ExportFile {
+                archive,
+                journal,
+                data_dir: eargs.data_dir.clone()
+            }, completed, parts));
+        }
+        progress_fun("Previous export not found, starting a new export");
+    }
+    let journal = ExportJournal::create(journal_path, &eargs.dbname)?;
+    let archive = ArchiveWriter::create(dest_file_path, dirname)?;
+    let mut export_file = ExportFile {
+        archive,
+        journal,
+        data_dir: eargs.data_dir.clone()
+    };
+    if eargs.encryption.is_enabled() {
+        add_encryption_entry(progress_fun, eargs, &mut export_file)?;
+    }
+    Ok((export_file, eargs.tables.iter().map(|_| false).collect(), Vec::new()))
 }
 
 pub fn run_export<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, eargs: &ExportArgs) -> ExportResult {
     progress_fun("Running export ...");
 
-    // ensure no dest dir
-    let (dest_dir, filename) = match prepare_dest_dir(&eargs.parent_dir, &eargs.dest_filename) {
-        Ok(tup) => tup,
-        Err(e) => return ExportResult::failure(e.to_string())
-    };
-    let dest_file = Path::new(&eargs.parent_dir).join(Path::new(&filename)).to_string_lossy().to_string();
+    let (dirname, filename) = export_file_names(&eargs.dest_filename);
+    let dest_file_path = Path::new(&eargs.parent_dir).join(Path::new(&filename));
+    let dest_file = dest_file_path.to_string_lossy().to_string();
     progress_fun(&format!("Export file: {}", dest_file));
+    // key is loaded before the export to not fail at the end
+    let signing_key = if eargs.signing_key_file.is_empty() {
+        None
+    } else {
+        match load_signing_key(&eargs.signing_key_file) {
+            Ok(key) => Some(key),
+            Err(e) => return ExportResult::failure(e.to_string())
+        }
+    };
+    if !eargs.data_dir.is_empty() {
+        // data files outside of the export file would be left unencrypted
+        let res = if eargs.encryption.is_enabled() {
+            Err(TransferError::from_str("Data directory cannot be used with encrypted export"))
+        } else {
+            fs::create_dir_all(&eargs.data_dir).map_err(TransferError::from)
+        };
+        if let Err(e) = res {
+            return ExportResult::failure(e.to_string());
+        }
+        progress_fun(&format!("Data directory: {}", &eargs.data_dir));
+    }
+    let journal_path = Path::new(&eargs.parent_dir).join(format!("{}.journal", &dirname));
+    let (export_file, completed, parts) = match open_export_file(progress_fun, eargs, &dest_file_path, &dirname, &journal_path) {
+        Ok(tup) => (Mutex::new(tup.0), tup.1, tup.2),
+        Err(e) => return ExportResult::failure(format!(
+            "Error opening export file, path: {}, error: {}", &dest_file, e))
+    };
 
     // spawn and wait
-    progress_fun("Running bcp ....");
-    if let Err(e) = export_tables(progress_fun, cc, eargs, &dest_dir) {
+    if eargs.schema_only {
+        progress_fun("Running schema export ....");
+    } else if DataFormat::Bcp != eargs.data_format {
+        progress_fun(&format!("Running {} export ....", eargs.data_format.label()));
+    } else if eargs.native_tds {
+        progress_fun("Running TDS export ....");
+    } else {
+        progress_fun("Running bcp ....");
+    }
+    let res = prepare_work_dir(eargs, &dirname)
+        .and_then(|work_dir| {
+            let res = export_tables(progress_fun, cc, eargs, &work_dir, &export_file, &completed, parts);
+            remove_work_dir(&work_dir);
+            res
+        })
+        .and_then(|_| export_database_files(progress_fun, cc, eargs, &export_file))
+        .and_then(|_| export_manifest(progress_fun, cc, eargs, &export_file, signing_key.as_ref()));
+    let ExportFile { archive, journal, .. } = match export_file.into_inner() {
+        Ok(export_file) => export_file,
+        Err(_) => return ExportResult::failure("Error accessing export file".to_string())
+    };
+    if let Err(e) = res {
+        if archive.close_partial() {
+            progress_fun(&format!("Exported tables are kept in file: {}, run the export again with 'resume' option to continue",
+                                  ArchiveWriter::part_path(&dest_file_path).to_string_lossy()));
+        } else {
+            journal.remove();
+        }
         return ExportResult::failure(e.to_string());
     };
 
-    // zip results
-    progress_fun("Zipping destination directory ....");
-    if let Err(e) = zip_dest_directory(progress_fun, &dest_dir, &filename) {
+    progress_fun("Finalizing export file ....");
+    if let Err(e) = archive.finish() {
         return ExportResult::failure(format!(
-            "Error zipping destination directory, path: {}, error: {}", &dest_dir, e));
+            "Error finalizing export file, path: {}, error: {}", &dest_file, e));
     };
+    journal.remove();
 
     progress_fun("Export complete");
     ExportResult::success()
@@ -16,18 +16,29 @@
 
 use super::*;
 
+use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::io::BufWriter;
+use std::io::Read;
 use std::os::windows::process::CommandExt;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
 
 use flate2::bufread::GzDecoder;
+use tiberius::Client;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_util::compat::Compat;
 use zip::ZipArchive;
+use zip::result::ZipError;
 
 #[derive(Default, Clone)]
 pub struct ImportArgs {
@@ -35,6 +46,50 @@ pub struct ImportArgs {
     pub tables: Vec<TableWithSize>,
     pub import_file: String,
     pub work_dir: String,
+    pub native_tds: bool,
+    pub jobs: usize,
+    pub resume: bool,
+    pub create_tables: bool,
+    pub replace_policy: ReplacePolicy,
+    pub create_indexes: bool,
+    pub create_modules: bool,
+    pub reseed: bool,
+    pub create_security: bool,
+    // source database user name to target server login name
+    pub user_mapping: HashMap<String, String>,
+    // required for encrypted export files
+    pub encryption: EncryptionSecret,
+    // path to Ed25519 public key, unsigned export files are rejected when specified
+    pub trusted_key_file: String,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum ReplacePolicy {
+    #[default]
+    Keep,
+    Truncate,
+    Recreate,
+}
+
+struct ImportArchive {
+    zip: ZipArchive<BufReader<File>>,
+    dirname: String,
+    key: Option<EncryptionKey>,
+}
+
+// table data is imported in units, one unit per data entry,
+// 'pending' and 'done' refer to the units
+#[derive(Default)]
+struct ImportSchedule {
+    pending: Vec<usize>,
+    running: usize,
+    done: Vec<bool>,
+    failed: bool,
+}
+
+struct ImportUnit {
+    table_idx: usize,
+    part: String,
 }
 
 #[derive(Default)]
@@ -56,17 +111,36 @@ impl ImportResult {
     }
 }
 
-fn unzip_table_files<P: Fn(&str)->()>(progress_fun: &P, table: &TableWithSize, import_file: &str, work_dir: &Path) -> Result<(PathBuf, PathBuf), TransferError> {
-    let import_file_path = Path::new(import_file);
-    let bcp_filename = format!("{}.{}.bcp", &table.schema, &table.table);
-    progress_fun(&format!("Unpacking {} into directory {}", &bcp_filename, work_dir.to_string_lossy().to_string()));
-    let zip_file = File::open(import_file_path)?;
+fn open_archive(import_file: &str, secret: &EncryptionSecret) -> Result<ImportArchive, TransferError> {
+    let zip_file = File::open(Path::new(import_file))?;
     let zip_reader = BufReader::new(zip_file);
-    let mut zip =  ZipArchive::new(zip_reader)?;
+    let mut zip = ZipArchive::new(zip_reader)?;
     let dirname: String = match zip.file_names().find(|nm| nm.ends_with("/")) {
         Some(dirname) => dirname.chars().take(dirname.len() - 1).collect(),
         None => return Err(TransferError::from_str("Directory entry not found in ZIP file"))
     };
+    let key = archive_key(&mut zip, &dirname, secret)?;
+    Ok(ImportArchive {
+        zip,
+        dirname,
+        key
+    })
+}
+
+fn unzip_table_files<P: Fn(&str)->()>(progress_fun: &P, table: &TableWithSize, part: &str, archive: &Mutex<ImportArchive>,
+                     work_dir: &Path) -> Result<(PathBuf, PathBuf), TransferError> {
+    let name_base = if part.is_empty() {
+        format!("{}.{}", &table.schema, &table.table)
+    } else {
+        format!("{}.{}.{}", &table.schema, &table.table, part)
+    };
+    let bcp_filename = format!("{}.bcp", name_base);
+    progress_fun(&format!("Unpacking {} into directory {}", &bcp_filename, work_dir.to_string_lossy().to_string()));
+    let mut guard = match archive.lock() {
+        Ok(guard) => guard,
+        Err(_) => return Err(TransferError::from_str("Error accessing ZIP file"))
+    };
+    let ImportArchive { zip, dirname, key } = &mut *guard;
     let bcp_gz_file = work_dir.join(&bcp_filename);
     {
         let file = File::create(&bcp_gz_file)?;
@@ -74,36 +148,33 @@ fn unzip_table_files<P: Fn(&str)->()>(progress_fun: &P, table: &TableWithSize, i
         let entry_name_base = format!("{}/{}", &dirname, &bcp_filename);
         let entry_name_gz = format!("{}.gz", &entry_name_base);
         let entry_name_zstd = format!("{}.zstd", &entry_name_base);
-        let entry_res = zip.by_name(&entry_name_zstd);
-        let entry = match entry_res {
-            Ok(entry) => entry,
-            Err(_) => {
-                std::mem::drop(entry_res);
-                match zip.by_name(&entry_name_gz) {
-                    Ok(entry) => entry,
-                    Err(_) => return Err(TransferError::from_string(
-                        format!("Table data entry not found in ZIP file, name: {} or {}", entry_name_zstd, entry_name_gz)))
-                }
-            }
-        };
-        let entry_name = entry.name().to_string();
-        let entry_buffered = BufReader::new(entry);
+        let entry_name = [&entry_name_zstd, &entry_name_gz, &entry_name_base].iter()
+            .find(|name| zip.file_names().any(|nm| nm == name.as_str()))
+            .map(|name| name.to_string())
+            .ok_or(TransferError::from_string(format!(
+                "Table data entry not found in ZIP file, name: {}, {} or {}", entry_name_zstd, entry_name_gz, entry_name_base)))?;
+        let entry = zip.by_name(&entry_name)?;
+        let mut entry_buffered = BufReader::new(decrypting_reader(entry, key.as_ref(), &entry_name));
         if entry_name.ends_with(".zstd") {
             let mut entry_decomp = BufReader::new(zstd::Decoder::new(entry_buffered)?);
             std::io::copy(&mut entry_decomp, &mut writer)?;
-        } else {
+        } else if entry_name.ends_with(".gz") {
             let mut entry_decomp = BufReader::new(GzDecoder::new(entry_buffered));
             std::io::copy(&mut entry_decomp, &mut writer)?;
+        } else {
+            std::io::copy(&mut entry_buffered, &mut writer)?;
         };
     }
 
+    // parts share the table format file, it is unpacked for each part separately
     let format_filename = format!("{}.{}.xml", &table.schema, &table.table);
-    let format_file = work_dir.join(&format_filename);
+    let format_file = work_dir.join(format!("{}.xml", name_base));
     {
         let file = File::create(&format_file)?;
         let mut writer = BufWriter::new(file);
-        let entry = zip.by_name(&format!("{}/{}", &dirname, &format_filename))?;
-        let mut entry_buffered = BufReader::new(entry);
+        let format_entry_name = format!("{}/{}", &dirname, &format_filename);
+        let entry = zip.by_name(&format_entry_name)?;
+        let mut entry_buffered = BufReader::new(decrypting_reader(entry, key.as_ref(), &format_entry_name));
         std::io::copy(&mut entry_buffered, &mut writer)?;
     }
     Ok((bcp_gz_file, format_file))
@@ -185,10 +256,545 @@ fn run_bcp<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, dbname: &str,
     Ok(())
 }
 
-fn import_tables<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, iargs: &ImportArgs, work_dir: &Path) -> Result<(), TransferError> {
+fn clean_table<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+               table: &TableWithSize, reason: &str) -> Result<(), TransferError> {
+    progress_fun(&format!("Removing rows {}: {}.{}", reason, &table.schema, &table.table));
+    let name = quote_table(&table.schema, &table.table);
+    runtime.block_on(async {
+        // truncate is not allowed on tables referenced by foreign keys
+        if client.execute(format!("truncate table {}", &name), &[]).await.is_err() {
+            client.execute(format!("delete from {}", &name), &[]).await?;
+        }
+        Ok(())
+    })
+}
+
+fn clean_table_part<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, archive: &Mutex<ImportArchive>,
+                    table: &TableWithSize, part: &str) -> Result<(), TransferError> {
+    let parts_filename = format!("{}.{}.parts.txt", &table.schema, &table.table);
+    let parts = match read_archive_text(archive, &parts_filename)? {
+        Some(text) => parse_parts(&text)?,
+        None => Vec::new()
+    };
+    let predicate = match parts.iter().find(|p| p.name == part) {
+        Some(p) => p.predicate.clone(),
+        None => return Err(TransferError::from_string(format!(
+            "Key range not found in ZIP file, table: {}.{}, part: {}", &table.schema, &table.table, part)))
+    };
+    progress_fun(&format!("Removing rows left by previous import attempt: {}.{}, {}", &table.schema, &table.table, &predicate));
+    let sql = format!("delete from {} where {}", quote_table(&table.schema, &table.table), &predicate);
+    runtime.block_on(async {
+        client.execute(sql, &[]).await?;
+        Ok(())
+    })
+}
+
+// 'bcp in' with XML format file cannot skip table columns
+fn format_covers_table(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, table: &TableWithSize,
+                       format_file: &Path) -> Result<bool, TransferError> {
+    let format = BcpFormat::read_file(format_file)?;
+    let names = import_native::load_column_names(runtime, client, &table.schema, &table.table)?;
+    Ok(format.columns.len() == names.len() &&
+        format.columns.iter().zip(names.iter()).all(|(c, n)| c.name.eq_ignore_ascii_case(n)))
+}
+
+fn read_archive_text(archive: &Mutex<ImportArchive>, filename: &str) -> Result<Option<String>, TransferError> {
+    let mut guard = match archive.lock() {
+        Ok(guard) => guard,
+        Err(_) => return Err(TransferError::from_str("Error accessing ZIP file"))
+    };
+    let ImportArchive { zip, dirname, key } = &mut *guard;
+    let entry_name = format!("{}/{}", &dirname, filename);
+    let entry = match zip.by_name(&entry_name) {
+        Ok(entry) => entry,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into())
+    };
+    let mut text = String::new();
+    decrypting_reader(entry, key.as_ref(), &entry_name).read_to_string(&mut text)?;
+    Ok(Some(text))
+}
+
+// tables created or truncated here are recorded in journal,
+// only their rows can be removed when resuming a partly loaded table
+fn prepare_tables<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, iargs: &ImportArgs, archive: &Mutex<ImportArchive>,
+                  touched: &Vec<bool>, journal: &mut ImportJournal) -> Result<(), TransferError> {
+    if !iargs.create_tables && ReplacePolicy::Keep == iargs.replace_policy {
+        return Ok(());
+    }
+    progress_fun("Checking target tables ...");
+    let runtime = cc.create_runtime()?;
+    let mut client = cc.open_connection_to_db(&runtime, &iargs.dbname)?;
+    // all table definitions are read before any table is changed,
+    // tables without definition are truncated
+    let mut actions: Vec<(&TableWithSize, bool, Option<String>)> = Vec::new();
+    for (idx, table) in iargs.tables.iter().enumerate() {
+        if touched[idx] {
+            continue;
+        }
+        let exists = table_exists(&runtime, &mut client, &table.schema, &table.table)?;
+        if exists {
+            match iargs.replace_policy {
+                ReplacePolicy::Keep => continue,
+                ReplacePolicy::Truncate => {
+                    actions.push((table, exists, None));
+                    continue;
+                },
+                ReplacePolicy::Recreate => {}
+            }
+        } else if !iargs.create_tables {
+            continue;
+        }
+        let ddl_filename = format!("{}.{}.ddl.sql", &table.schema, &table.table);
+        let ddl = match read_archive_text(archive, &ddl_filename)? {
+            Some(ddl) => ddl,
+            None => return Err(TransferError::from_string(format!(
+                "Table definition not found in ZIP file, table: {}.{}", &table.schema, &table.table)))
+        };
+        actions.push((table, exists, Some(ddl)));
+    }
+    for (table, exists, ddl) in actions.iter() {
+        match (exists, ddl) {
+            (_, None) => clean_table(progress_fun, &runtime, &mut client, table, "from existing table")?,
+            (true, Some(ddl)) => {
+                progress_fun(&format!("Recreating existing table: {}.{}", &table.schema, &table.table));
+                recreate_table(&runtime, &mut client, &table.schema, &table.table, ddl)?;
+            },
+            (false, Some(ddl)) => {
+                if create_schema_if_missing(&runtime, &mut client, &table.schema)? {
+                    progress_fun(&format!("Created schema: {}", &table.schema));
+                }
+                progress_fun(&format!("Creating table: {}.{}", &table.schema, &table.table));
+                execute_ddl(&runtime, &mut client, ddl)?;
+            }
+        };
+        journal.mark_prepared(&table.schema, &table.table)?;
+    }
+    Ok(())
+}
+
+fn apply_post_data<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, iargs: &ImportArgs,
+                   archive: &Mutex<ImportArchive>) -> Result<(), TransferError> {
+    let mut objects: Vec<PostDataObject> = Vec::new();
     for table in iargs.tables.iter() {
-        let (bcp_file, format_file) = unzip_table_files(progress_fun, &table, &iargs.import_file, work_dir)?;
-        run_bcp(progress_fun, cc, &iargs.dbname, &table, &bcp_file, &format_file, work_dir)?;
+        let post_filename = format!("{}.{}.post.sql", &table.schema, &table.table);
+        if let Some(text) = read_archive_text(archive, &post_filename)? {
+            objects.extend(parse_post_data(&table.schema, &table.table, &text)?);
+        }
+    }
+    progress_fun(&format!("Creating {} indexes and constraints ...", objects.len()));
+    // keys are created before the foreign keys that reference them
+    objects.sort_by_key(|o| o.kind);
+    let runtime = cc.create_runtime()?;
+    let mut client = cc.open_connection_to_db(&runtime, &iargs.dbname)?;
+    let mut failed = 0;
+    for obj in objects.iter() {
+        let label = format!("{} {} on {}.{}", obj.kind.label().replace("_", " "), &obj.name, &obj.schema, &obj.table);
+        if post_data_exists(&runtime, &mut client, obj)? {
+            progress_fun(&format!("Already exists: {}", label));
+            continue;
+        }
+        match execute_ddl(&runtime, &mut client, &obj.sql) {
+            Ok(()) => progress_fun(&format!("Created: {}", label)),
+            Err(e) => {
+                failed += 1;
+                progress_fun(&format!("Failed: {}, error: {}", label, e));
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(TransferError::from_string(format!(
+            "Error creating indexes and constraints, failed objects: {} of {}", failed, objects.len())));
+    }
+    Ok(())
+}
+
+fn apply_modules<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, iargs: &ImportArgs,
+                 archive: &Mutex<ImportArchive>) -> Result<(), TransferError> {
+    let modules = match read_archive_text(archive, "modules.sql")? {
+        Some(text) => parse_sql_modules(&text)?,
+        None => {
+            progress_fun("Modules not found in ZIP file");
+            return Ok(());
+        }
+    };
+    progress_fun(&format!("Creating {} views, functions, procedures and triggers ...", modules.len()));
+    let runtime = cc.create_runtime()?;
+    let mut client = cc.open_connection_to_db(&runtime, &iargs.dbname)?;
+    let mut failed = Vec::new();
+    for m in modules.iter() {
+        if module_exists(&runtime, &mut client, m)? {
+            progress_fun(&format!("Already exists: {}", m.label()));
+            continue;
+        }
+        match execute_ddl(&runtime, &mut client, &m.definition) {
+            Ok(()) => progress_fun(&format!("Created: {}", m.label())),
+            Err(e) => {
+                progress_fun(&format!("Failed: {}, error: {}", m.label(), e));
+                failed.push(m.label());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        progress_fun(&format!("Modules failed to compile: {}", failed.len()));
+        for label in failed.iter() {
+            progress_fun(&format!("  {}", label));
+        }
+        return Err(TransferError::from_string(format!(
+            "Error creating modules, failed objects: {} of {}", failed.len(), modules.len())));
+    }
+    Ok(())
+}
+
+fn apply_reseed<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, iargs: &ImportArgs,
+                archive: &Mutex<ImportArchive>) -> Result<(), TransferError> {
+    let statements = match read_archive_text(archive, "reseed.sql")? {
+        Some(text) => parse_reseed(&text)?,
+        None => {
+            progress_fun("Identity and sequence values not found in ZIP file");
+            return Ok(());
+        }
+    };
+    // identities are only reseeded for the tables that were imported
+    let statements: Vec<_> = statements.into_iter()
+        .filter(|st| !st.is_identity || iargs.tables.iter().any(|t| t.schema == st.schema && t.table == st.name))
+        .collect();
+    progress_fun(&format!("Reseeding {} identities and sequences ...", statements.len()));
+    let runtime = cc.create_runtime()?;
+    let mut client = cc.open_connection_to_db(&runtime, &iargs.dbname)?;
+    let mut failed = Vec::new();
+    for st in statements.iter() {
+        match execute_ddl(&runtime, &mut client, &st.sql) {
+            Ok(()) => progress_fun(&format!("Reseeded: {}", st.label())),
+            Err(e) => {
+                progress_fun(&format!("Failed: {}, error: {}", st.label(), e));
+                failed.push(st.label());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        return Err(TransferError::from_string(format!(
+            "Error reseeding identities and sequences, failed objects: {} of {}", failed.len(), statements.len())));
+    }
+    Ok(())
+}
+
+fn apply_security<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, iargs: &ImportArgs,
+                  archive: &Mutex<ImportArchive>) -> Result<(), TransferError> {
+    let objects = match read_archive_text(archive, "security.sql")? {
+        Some(text) => parse_security(&text)?,
+        None => {
+            progress_fun("Roles, users and permissions not found in ZIP file");
+            return Ok(());
+        }
+    };
+    progress_fun(&format!("Creating {} roles, users, memberships and permissions ...", objects.len()));
+    let runtime = cc.create_runtime()?;
+    let mut client = cc.open_connection_to_db(&runtime, &iargs.dbname)?;
+    let mut failed = Vec::new();
+    for obj in objects.iter() {
+        let obj = match (obj.kind, iargs.user_mapping.get(&obj.name)) {
+            (SecurityKind::User, Some(login)) => obj.with_login(login),
+            _ => obj.clone()
+        };
+        let is_principal = SecurityKind::Role == obj.kind || SecurityKind::User == obj.kind;
+        if is_principal && principal_exists(&runtime, &mut client, &obj.name)? {
+            progress_fun(&format!("Already exists: {}", obj.label()));
+            continue;
+        }
+        match execute_ddl(&runtime, &mut client, &obj.sql) {
+            Ok(()) => progress_fun(&format!("Applied: {}", obj.label())),
+            Err(e) => {
+                progress_fun(&format!("Failed: {}, error: {}", obj.label(), e));
+                failed.push(obj.label());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        progress_fun(&format!("Security objects failed to apply: {}", failed.len()));
+        for label in failed.iter() {
+            progress_fun(&format!("  {}", label));
+        }
+        return Err(TransferError::from_string(format!(
+            "Error applying security, failed objects: {} of {}", failed.len(), objects.len())));
+    }
+    Ok(())
+}
+
+fn update_journal<F: FnOnce(&mut ImportJournal) -> Result<(), TransferError>>(journal: &Mutex<ImportJournal>, fun: F) -> Result<(), TransferError> {
+    match journal.lock() {
+        Ok(mut guard) => fun(&mut guard),
+        Err(_) => Err(TransferError::from_str("Error accessing import journal"))
+    }
+}
+
+fn import_table<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, iargs: &ImportArgs, work_dir: &Path, archive: &Mutex<ImportArchive>,
+                journal: &Mutex<ImportJournal>, table: &TableWithSize, part: &str, conn: &mut Option<(Runtime, Client<Compat<TcpStream>>)>) -> Result<(), TransferError> {
+    let (bcp_file, format_file) = unzip_table_files(progress_fun, table, part, archive, work_dir)?;
+    let (started, prepared) = match journal.lock() {
+        Ok(guard) => (guard.is_started(&table.schema, &table.table, part), guard.is_prepared(&table.schema, &table.table)),
+        Err(_) => return Err(TransferError::from_str("Error accessing import journal"))
+    };
+    if started {
+        // rows that existed before the import cannot be told apart from the loaded ones
+        if !prepared {
+            return Err(TransferError::from_string(format!(
+                "Table was partly loaded by previous import attempt: {}.{}, remove the loaded rows manually \
+                or run the import with 'truncate' or 'recreate' replace policy", &table.schema, &table.table)));
+        }
+        let (runtime, client) = cc.open_cached_connection(&iargs.dbname, conn)?;
+        if part.is_empty() {
+            clean_table(progress_fun, runtime, client, table, "left by previous import attempt")?;
+        } else {
+            clean_table_part(progress_fun, runtime, client, archive, table, part)?;
+        }
+    }
+    update_journal(journal, |jr| jr.mark_started(&table.schema, &table.table, part))?;
+    let native_tds = if iargs.native_tds {
+        true
+    } else {
+        let (runtime, client) = cc.open_cached_connection(&iargs.dbname, conn)?;
+        let covers = format_covers_table(runtime, client, table, &format_file)?;
+        if !covers {
+            progress_fun("Data file does not contain all table columns, using TDS bulk load");
+        }
+        !covers
+    };
+    if native_tds {
+        let (runtime, client) = cc.open_cached_connection(&iargs.dbname, conn)?;
+        import_native::run_native_import(progress_fun, runtime, client, table, &bcp_file, &format_file)?;
+    } else {
+        run_bcp(progress_fun, cc, &iargs.dbname, table, &bcp_file, &format_file, work_dir)?;
+    }
+    update_journal(journal, |jr| jr.mark_done(&table.schema, &table.table, part))?;
+    let _ = fs::remove_file(&bcp_file);
+    let _ = fs::remove_file(&format_file);
+    Ok(())
+}
+
+fn next_scheduled_unit(schedule: &Mutex<ImportSchedule>, cvar: &Condvar, units: &Vec<ImportUnit>,
+                       parents: &Vec<Vec<usize>>, table_units: &Vec<Vec<usize>>) -> Option<usize> {
+    let mut st = match schedule.lock() {
+        Ok(st) => st,
+        Err(_) => return None
+    };
+    loop {
+        if st.failed || st.pending.is_empty() {
+            return None;
+        }
+        // unit is ready when all parts of the parent tables are imported
+        let ready = st.pending.iter().position(|idx| parents[units[*idx].table_idx].iter()
+            .all(|p| table_units[*p].iter().all(|u| st.done[*u])));
+        // nothing is running and nothing is ready means a dependency cycle
+        let pos = match ready {
+            Some(pos) => Some(pos),
+            None if 0 == st.running => Some(0),
+            None => None
+        };
+        if let Some(pos) = pos {
+            let idx = st.pending.remove(pos);
+            st.running += 1;
+            return Some(idx);
+        }
+        st = match cvar.wait(st) {
+            Ok(st) => st,
+            Err(_) => return None
+        };
+    }
+}
+
+fn import_worker(cc: &TdsConnConfig, iargs: &ImportArgs, work_dir: &Path, archive: &Mutex<ImportArchive>, journal: &Mutex<ImportJournal>,
+                 units: &Vec<ImportUnit>, parents: &Vec<Vec<usize>>, table_units: &Vec<Vec<usize>>, schedule: &Mutex<ImportSchedule>,
+                 cvar: &Condvar, results: &Mutex<Vec<Option<Result<(), TransferError>>>>, sender: mpsc::Sender<String>) {
+    let mut conn = None;
+    while let Some(idx) = next_scheduled_unit(schedule, cvar, units, parents, table_units) {
+        let unit = &units[idx];
+        let table = &iargs.tables[unit.table_idx];
+        let tag = if unit.part.is_empty() {
+            format!("{}.{}", &table.schema, &table.table)
+        } else {
+            format!("{}.{} {}", &table.schema, &table.table, &unit.part)
+        };
+        let progress_fun = |st: &str| {
+            // empty message is used to flush progress output
+            let msg = if st.is_empty() { String::new() } else { format!("[{}] {}", tag, st) };
+            let _ = sender.send(msg);
+        };
+        let res = import_table(&progress_fun, cc, iargs, work_dir, archive, journal, table, &unit.part, &mut conn);
+        if let Ok(mut st) = schedule.lock() {
+            st.running -= 1;
+            st.done[idx] = res.is_ok();
+            if res.is_err() {
+                st.failed = true;
+            }
+        }
+        cvar.notify_all();
+        if let Ok(mut results) = results.lock() {
+            results[idx] = Some(res);
+        }
+    }
+}
+
+fn import_tables<P: Fn(&str)->()>(progress_fun: &P, cc: &TdsConnConfig, iargs: &ImportArgs, work_dir: &Path) -> Result<(), TransferError> {
+    let trusted_key = if iargs.trusted_key_file.is_empty() {
+        None
+    } else {
+        Some(load_trusted_key(&iargs.trusted_key_file)?)
+    };
+    let mut archive = open_archive(&iargs.import_file, &iargs.encryption)?;
+    if let Some(key) = &trusted_key {
+        progress_fun("Verifying export file signature and checksums ...");
+        verify_archive_entries(progress_fun, &mut archive.zip, &archive.dirname, archive.key.as_ref(), Some(key))?;
+    }
+    let identity = archive_identity(&mut archive.zip, fs::metadata(&iargs.import_file)?.len())?;
+    let archive = Mutex::new(archive);
+    if let Some(text) = read_archive_text(&archive, MANIFEST_FILENAME)? {
+        let manifest = Manifest::parse(&text)?;
+        if manifest.format_version > MANIFEST_FORMAT_VERSION {
+            return Err(TransferError::from_string(format!(
+                "Unsupported archive format version: {}, archive was created by a newer version: {}",
+                manifest.format_version, &manifest.tool_version)));
+        }
+        if !manifest.options.data_format.is_empty() && "bcp" != manifest.options.data_format {
+            return Err(TransferError::from_string(format!(
+                "Export file contains data in '{}' format, only 'bcp' data can be imported", &manifest.options.data_format)));
+        }
+        if manifest.options.external_data {
+            return Err(TransferError::from_str(
+                "Export file data was written to a separate directory and cannot be imported"));
+        }
+        progress_fun(&format!("Archive of database: {}, server: {}, exported at: {}",
+                              &manifest.source.database, &manifest.source.server, &manifest.exported_at));
+    }
+    let journal_path = PathBuf::from(format!("{}.journal", &iargs.work_dir));
+    let mut journal = if iargs.resume && journal_path.exists() {
+        progress_fun(&format!("Resuming import using journal: {}", journal_path.to_string_lossy()));
+        ImportJournal::open(&journal_path, &identity, &iargs.dbname)?
+    } else {
+        if iargs.resume {
+            progress_fun("Previous import not found, starting a new import");
+        }
+        ImportJournal::create(&journal_path, &identity, &iargs.dbname)?
+    };
+    let mut units = Vec::new();
+    let mut table_units: Vec<Vec<usize>> = Vec::new();
+    for (idx, table) in iargs.tables.iter().enumerate() {
+        // tables without data are only created by 'prepare_tables'
+        let parts = if table.schema_only {
+            Vec::new()
+        } else if table.parts.is_empty() {
+            vec!(String::new())
+        } else {
+            table.parts.clone()
+        };
+        table_units.push((units.len()..units.len() + parts.len()).collect());
+        units.extend(parts.into_iter().map(|part| ImportUnit {
+            table_idx: idx,
+            part
+        }));
+    }
+    let completed: Vec<bool> = units.iter()
+        .map(|u| journal.is_done(&iargs.tables[u.table_idx].schema, &iargs.tables[u.table_idx].table, &u.part))
+        .collect();
+    // tables with some of the parts imported are not touched before the import
+    let touched: Vec<bool> = table_units.iter()
+        .map(|tu| tu.iter().any(|u| completed[*u]))
+        .collect();
+    prepare_tables(progress_fun, cc, iargs, &archive, &touched, &mut journal)?;
+    let journal = Mutex::new(journal);
+    let names: Vec<(String, String)> = iargs.tables.iter()
+        .map(|t| (t.schema.clone(), t.table.clone()))
+        .collect();
+    let parents = match load_table_dependencies(cc, &iargs.dbname) {
+        Ok(deps) => parent_indices(&names, &deps),
+        Err(e) => {
+            progress_fun(&format!("Foreign keys are not loaded, tables are imported in any order, error: {}", e));
+            names.iter().map(|_| Vec::new()).collect()
+        }
+    };
+    let mut pending: Vec<usize> = (0..units.len()).filter(|idx| !completed[*idx]).collect();
+    pending.sort_by(|a, b| iargs.tables[units[*b].table_idx].size_bytes.cmp(&iargs.tables[units[*a].table_idx].size_bytes));
+    let schedule = Mutex::new(ImportSchedule {
+        pending,
+        done: completed.clone(),
+        ..Default::default()
+    });
+    let cvar = Condvar::new();
+    let results: Mutex<Vec<Option<Result<(), TransferError>>>> = Mutex::new(
+        units.iter().map(|_| None).collect());
+    let count = completed.iter().filter(|c| !**c).count();
+    let jobs = iargs.jobs.max(1).min(count.max(1));
+    progress_fun(&format!("Importing {} data files using {} worker(s)", count, jobs));
+
+    let (sender, receiver) = mpsc::channel::<String>();
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let archive = &archive;
+            let journal = &journal;
+            let units = &units;
+            let parents = &parents;
+            let table_units = &table_units;
+            let schedule = &schedule;
+            let cvar = &cvar;
+            let results = &results;
+            scope.spawn(move || {
+                import_worker(cc, iargs, work_dir, archive, journal, units, parents, table_units, schedule, cvar, results, sender);
+            });
+        }
+        std::mem::drop(sender);
+        for msg in receiver.iter() {
+            progress_fun(&msg);
+        }
+    });
+
+    let results = match results.into_inner() {
+        Ok(results) => results,
+        Err(_) => return Err(TransferError::from_str("Import worker failure"))
+    };
+    let mut first_error: Option<TransferError> = None;
+    for (idx, (unit, res)) in units.iter().zip(results.into_iter()).enumerate() {
+        let table = &iargs.tables[unit.table_idx];
+        let label = if unit.part.is_empty() {
+            format!("{}.{}", &table.schema, &table.table)
+        } else {
+            format!("{}.{} {}", &table.schema, &table.table, &unit.part)
+        };
+        if completed[idx] {
+            progress_fun(&format!("Already imported: {}", label));
+            continue;
+        }
+        match res {
+            Some(Ok(())) => progress_fun(&format!("Imported: {}", label)),
+            Some(Err(e)) => {
+                progress_fun(&format!("Failed: {}, error: {}", label, e));
+                if first_error.is_none() {
+                    first_error = Some(TransferError::from_string(format!(
+                        "Error importing table: {}, message: {}", label, e)));
+                }
+            },
+            None => progress_fun(&format!("Skipped: {}", label))
+        }
+    }
+    if let Some(e) = first_error {
+        progress_fun(&format!("Import state is kept in file: {}, run the import again with 'resume' option to continue",
+                              journal_path.to_string_lossy()));
+        return Err(e);
+    }
+    if iargs.reseed {
+        apply_reseed(progress_fun, cc, iargs, &archive)?;
+    }
+    if iargs.create_indexes {
+        apply_post_data(progress_fun, cc, iargs, &archive)?;
+    }
+    if iargs.create_modules {
+        apply_modules(progress_fun, cc, iargs, &archive)?;
+    }
+    if iargs.create_security {
+        apply_security(progress_fun, cc, iargs, &archive)?;
+    }
+    if let Ok(journal) = journal.into_inner() {
+        journal.remove();
     }
     Ok(())
 }
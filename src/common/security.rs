@@ -0,0 +1,281 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use tiberius::Client;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_util::compat::Compat;
+
+// variants are listed in the order they are applied on import
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum SecurityKind {
+    Role,
+    User,
+    Membership,
+    Permission,
+}
+
+impl SecurityKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SecurityKind::Role => "role",
+            SecurityKind::User => "user",
+            SecurityKind::Membership => "membership",
+            SecurityKind::Permission => "permission",
+        }
+    }
+
+    fn from_label(label: &str) -> Option<Self> {
+        match label {
+            "role" => Some(SecurityKind::Role),
+            "user" => Some(SecurityKind::User),
+            "membership" => Some(SecurityKind::Membership),
+            "permission" => Some(SecurityKind::Permission),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SecurityObject {
+    pub kind: SecurityKind,
+    pub name: String,
+    pub login: String,
+    pub default_schema: String,
+    pub sql: String,
+}
+
+impl SecurityObject {
+    fn new(kind: SecurityKind, name: &str, sql: String) -> Self {
+        Self {
+            kind,
+            name: name.to_string(),
+            login: String::new(),
+            default_schema: String::new(),
+            sql
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self.kind {
+            SecurityKind::Role | SecurityKind::User => format!("{} {}", self.kind.label(), &self.name),
+            _ => self.sql.clone()
+        }
+    }
+
+    // users are mapped to the logins that exist on the target server
+    pub fn with_login(&self, login: &str) -> Self {
+        let mut res = self.clone();
+        res.login = login.to_string();
+        res.sql = create_user_sql(&self.name, login, &self.default_schema);
+        res
+    }
+}
+
+fn create_user_sql(name: &str, login: &str, default_schema: &str) -> String {
+    let mut sql = if login.is_empty() {
+        format!("create user {} without login", quote_ident(name))
+    } else {
+        format!("create user {} for login {}", quote_ident(name), quote_ident(login))
+    };
+    if !default_schema.is_empty() {
+        sql.push_str(&format!(" with default_schema = {}", quote_ident(default_schema)));
+    }
+    sql
+}
+
+// built-in principals are present in every database
+const SYSTEM_PRINCIPALS: &str = "('public', 'dbo', 'guest', 'INFORMATION_SCHEMA', 'sys')";
+
+async fn load_principals(client: &mut Client<Compat<TcpStream>>) -> Result<Vec<SecurityObject>, TransferError> {
+    let query = tiberius::Query::new(format!("\
+            select
+                p.name,
+                p.type,
+                suser_sname(p.sid) as login_name,
+                p.default_schema_name
+            from sys.database_principals as p
+            where ((p.type = 'R' and p.is_fixed_role = 0) or p.type in ('S', 'U', 'G'))
+            and p.name not in {}
+            order by p.principal_id", SYSTEM_PRINCIPALS));
+    let rows = query.query(client).await?.into_first_result().await?;
+    let msg = "Database principals select error";
+    let mut res = Vec::new();
+    for row in rows.iter() {
+        let name: &str = row.get(0).ok_or(TransferError::from_str(msg))?;
+        let ptype: &str = row.get(1).ok_or(TransferError::from_str(msg))?;
+        if "R" == ptype.trim() {
+            res.push(SecurityObject::new(SecurityKind::Role, name, format!("create role {}", quote_ident(name))));
+        } else {
+            let login: &str = row.get(2).unwrap_or("");
+            let default_schema: &str = row.get(3).unwrap_or("");
+            res.push(SecurityObject {
+                kind: SecurityKind::User,
+                name: name.to_string(),
+                login: login.to_string(),
+                default_schema: default_schema.to_string(),
+                sql: create_user_sql(name, login, default_schema)
+            });
+        }
+    }
+    Ok(res)
+}
+
+async fn load_memberships(client: &mut Client<Compat<TcpStream>>) -> Result<Vec<SecurityObject>, TransferError> {
+    let query = tiberius::Query::new(format!("\
+            select
+                r.name as role_name,
+                m.name as member_name
+            from sys.database_role_members as rm
+            join sys.database_principals as r
+                on r.principal_id = rm.role_principal_id
+            join sys.database_principals as m
+                on m.principal_id = rm.member_principal_id
+            where m.name not in {}
+            order by r.name, m.name", SYSTEM_PRINCIPALS));
+    let rows = query.query(client).await?.into_first_result().await?;
+    let msg = "Role members select error";
+    let mut res = Vec::new();
+    for row in rows.iter() {
+        let role: &str = row.get(0).ok_or(TransferError::from_str(msg))?;
+        let member: &str = row.get(1).ok_or(TransferError::from_str(msg))?;
+        let sql = format!("alter role {} add member {}", quote_ident(role), quote_ident(member));
+        res.push(SecurityObject::new(SecurityKind::Membership, role, sql));
+    }
+    Ok(res)
+}
+
+async fn load_permissions(client: &mut Client<Compat<TcpStream>>) -> Result<Vec<SecurityObject>, TransferError> {
+    // only database, object and schema permissions are transferred
+    let query = tiberius::Query::new(format!("\
+            select
+                dp.class,
+                dp.state_desc,
+                dp.permission_name,
+                g.name as grantee_name,
+                object_schema_name(dp.major_id) as object_schema,
+                object_name(dp.major_id) as object_name,
+                col_name(dp.major_id, dp.minor_id) as column_name,
+                schema_name(dp.major_id) as schema_name
+            from sys.database_permissions as dp
+            join sys.database_principals as g
+                on g.principal_id = dp.grantee_principal_id
+            where dp.class in (0, 1, 3)
+            and g.name not in ('dbo', 'guest', 'INFORMATION_SCHEMA', 'sys')
+            and (dp.class <> 1 or objectproperty(dp.major_id, 'IsMSShipped') = 0)
+            and (dp.class <> 3 or schema_name(dp.major_id) not in {})
+            order by dp.class, dp.major_id, dp.minor_id, g.name, dp.permission_name", SYSTEM_PRINCIPALS));
+    let rows = query.query(client).await?.into_first_result().await?;
+    let msg = "Database permissions select error";
+    let mut res = Vec::new();
+    for row in rows.iter() {
+        let class: u8 = row.get(0).ok_or(TransferError::from_str(msg))?;
+        let state: &str = row.get(1).ok_or(TransferError::from_str(msg))?;
+        let permission: &str = row.get(2).ok_or(TransferError::from_str(msg))?;
+        let grantee: &str = row.get(3).ok_or(TransferError::from_str(msg))?;
+        let securable = match class {
+            1 => {
+                let schema: &str = row.get(4).ok_or(TransferError::from_str(msg))?;
+                let object: &str = row.get(5).ok_or(TransferError::from_str(msg))?;
+                let column: Option<&str> = row.get(6);
+                match column {
+                    Some(col) => format!(" on {} ({})", quote_table(schema, object), quote_ident(col)),
+                    None => format!(" on {}", quote_table(schema, object))
+                }
+            },
+            3 => {
+                let schema: &str = row.get(7).ok_or(TransferError::from_str(msg))?;
+                format!(" on schema::{}", quote_ident(schema))
+            },
+            _ => String::new()
+        };
+        let sql = match state {
+            "GRANT_WITH_GRANT_OPTION" => format!("grant {}{} to {} with grant option", permission.to_lowercase(), securable, quote_ident(grantee)),
+            "DENY" => format!("deny {}{} to {}", permission.to_lowercase(), securable, quote_ident(grantee)),
+            _ => format!("grant {}{} to {}", permission.to_lowercase(), securable, quote_ident(grantee))
+        };
+        res.push(SecurityObject::new(SecurityKind::Permission, grantee, sql));
+    }
+    Ok(res)
+}
+
+fn security_to_sql(objects: &Vec<SecurityObject>) -> String {
+    let mut res = String::new();
+    for obj in objects.iter() {
+        res.push_str(&format!("-- security: {}\t{}\t{}\t{}\r\n{}\r\nGO\r\n",
+                              obj.kind.label(), &obj.name, &obj.login, &obj.default_schema, &obj.sql));
+    }
+    res
+}
+
+pub fn parse_security(text: &str) -> Result<Vec<SecurityObject>, TransferError> {
+    let mut objects = Vec::new();
+    let mut current: Option<SecurityObject> = None;
+    let mut lines: Vec<&str> = Vec::new();
+    for ln in text.lines() {
+        if let Some(header) = ln.strip_prefix("-- security: ") {
+            let parts: Vec<&str> = header.split('\t').collect();
+            let kind = SecurityKind::from_label(parts[0]).ok_or(TransferError::from_string(format!(
+                "Invalid security header: {}", ln)))?;
+            if parts.len() != 4 {
+                return Err(TransferError::from_string(format!(
+                    "Invalid security header: {}", ln)));
+            }
+            current = Some(SecurityObject {
+                kind,
+                name: parts[1].to_string(),
+                login: parts[2].to_string(),
+                default_schema: parts[3].to_string(),
+                sql: String::new()
+            });
+            lines.clear();
+        } else if "GO" == ln.trim() {
+            if let Some(mut obj) = current.take() {
+                obj.sql = lines.join("\r\n");
+                objects.push(obj);
+            }
+            lines.clear();
+        } else {
+            lines.push(ln);
+        }
+    }
+    objects.sort_by_key(|obj| obj.kind);
+    Ok(objects)
+}
+
+pub fn principal_exists(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, name: &str) -> Result<bool, TransferError> {
+    runtime.block_on(async {
+        let mut query = tiberius::Query::new("select count(*) from sys.database_principals where name = @P1");
+        query.bind(name.to_string());
+        let row = query.query(client).await?.into_row().await?;
+        Ok(row.and_then(|r| r.get::<i32, _>(0)).unwrap_or(0) > 0)
+    })
+}
+
+pub fn run_security<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime,
+                                     client: &mut Client<Compat<TcpStream>>) -> Result<(String, String), TransferError> {
+    progress_fun("Scripting roles, users and permissions ...");
+    let objects = runtime.block_on(async {
+        let mut objects = load_principals(client).await?;
+        objects.extend(load_memberships(client).await?);
+        objects.extend(load_permissions(client).await?);
+        Ok::<Vec<SecurityObject>, TransferError>(objects)
+    })?;
+    let security_filename = "security.sql".to_string();
+    Ok((security_filename, security_to_sql(&objects)))
+}
@@ -0,0 +1,74 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::fs;
+
+use ed25519_dalek::Signature;
+use ed25519_dalek::Signer;
+use ed25519_dalek::SigningKey;
+use ed25519_dalek::VerifyingKey;
+use ed25519_dalek::pkcs8::DecodePrivateKey;
+use ed25519_dalek::pkcs8::DecodePublicKey;
+
+// detached Ed25519 signature of the manifest, manifest contains checksums of all other entries
+pub static SIGNATURE_FILENAME: &str = "manifest.json.sig";
+
+fn read_key_file(path: &str) -> Result<String, TransferError> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(e) => Err(TransferError::from_string(format!(
+            "Error reading key file, path: {}, message: {}", path, e)))
+    }
+}
+
+// private key in PKCS#8 PEM format, as written by 'openssl genpkey -algorithm ed25519'
+pub fn load_signing_key(path: &str) -> Result<SigningKey, TransferError> {
+    match SigningKey::from_pkcs8_pem(&read_key_file(path)?) {
+        Ok(key) => Ok(key),
+        Err(e) => Err(TransferError::from_string(format!(
+            "Invalid Ed25519 private key, path: {}, message: {}", path, e)))
+    }
+}
+
+// public key in PEM format, as written by 'openssl pkey -pubout'
+pub fn load_trusted_key(path: &str) -> Result<VerifyingKey, TransferError> {
+    match VerifyingKey::from_public_key_pem(&read_key_file(path)?) {
+        Ok(key) => Ok(key),
+        Err(e) => Err(TransferError::from_string(format!(
+            "Invalid Ed25519 public key, path: {}, message: {}", path, e)))
+    }
+}
+
+// signature is stored as a hex string
+pub fn sign_manifest(key: &SigningKey, manifest_text: &str) -> String {
+    let signature = key.sign(manifest_text.as_bytes());
+    to_hex(&signature.to_bytes())
+}
+
+pub fn verify_signature(key: &VerifyingKey, manifest_text: &str, signature_text: &str) -> Result<(), TransferError> {
+    let bytes = from_hex(signature_text.trim())?;
+    let signature = match Signature::from_slice(&bytes) {
+        Ok(signature) => signature,
+        Err(_) => return Err(TransferError::from_str("Invalid export file signature"))
+    };
+    match key.verify_strict(manifest_text.as_bytes(), &signature) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(TransferError::from_str(
+            "Export file signature does not match the trusted key, file was signed with another key or was modified"))
+    }
+}
@@ -0,0 +1,200 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::collections::HashMap;
+
+use tiberius::Client;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_util::compat::Compat;
+
+#[derive(Default, Clone, Debug)]
+pub struct SqlModule {
+    pub object_id: i32,
+    pub schema: String,
+    pub name: String,
+    pub type_desc: String,
+    pub definition: String,
+}
+
+impl SqlModule {
+    pub fn label(&self) -> String {
+        format!("{} {}.{}", self.type_desc.to_lowercase().replace("_", " "), &self.schema, &self.name)
+    }
+}
+
+async fn load_modules<P: Fn(&str)->()>(progress_fun: &P, client: &mut Client<Compat<TcpStream>>) -> Result<Vec<SqlModule>, TransferError> {
+    let query = tiberius::Query::new("\
+            select
+                o.object_id,
+                schema_name(o.schema_id) as schema_name,
+                o.name,
+                o.type_desc,
+                m.definition
+            from sys.sql_modules as m
+            join sys.objects as o
+                on o.object_id = m.object_id
+            where o.is_ms_shipped = 0
+            order by o.object_id");
+    let rows = query.query(client).await?.into_first_result().await?;
+    let msg = "Modules select error";
+    let mut modules = Vec::new();
+    for row in rows.iter() {
+        let object_id: i32 = row.get(0).ok_or(TransferError::from_str(msg))?;
+        let schema: &str = row.get(1).ok_or(TransferError::from_str(msg))?;
+        let name: &str = row.get(2).ok_or(TransferError::from_str(msg))?;
+        let type_desc: &str = row.get(3).ok_or(TransferError::from_str(msg))?;
+        let definition: Option<&str> = row.get(4);
+        match definition {
+            Some(definition) => modules.push(SqlModule {
+                object_id,
+                schema: schema.to_string(),
+                name: name.to_string(),
+                type_desc: type_desc.to_string(),
+                definition: definition.to_string()
+            }),
+            // encrypted modules have no definition
+            None => progress_fun(&format!("Skipping module without definition: {}.{}", schema, name))
+        }
+    }
+    Ok(modules)
+}
+
+async fn load_module_dependencies(client: &mut Client<Compat<TcpStream>>) -> Result<Vec<(i32, i32)>, TransferError> {
+    let query = tiberius::Query::new("\
+            select distinct
+                d.referencing_id,
+                d.referenced_id
+            from sys.sql_expression_dependencies as d
+            where d.referenced_id is not null
+            and d.referencing_id <> d.referenced_id");
+    let rows = query.query(client).await?.into_first_result().await?;
+    let msg = "Module dependencies select error";
+    let mut deps = Vec::new();
+    for row in rows.iter() {
+        let referencing: i32 = row.get(0).ok_or(TransferError::from_str(msg))?;
+        let referenced: i32 = row.get(1).ok_or(TransferError::from_str(msg))?;
+        deps.push((referencing, referenced));
+    }
+    Ok(deps)
+}
+
+// modules are ordered so that referenced modules come first,
+// modules in dependency cycles are kept in their original order
+fn sort_modules(modules: Vec<SqlModule>, deps: &Vec<(i32, i32)>) -> Vec<SqlModule> {
+    let positions: HashMap<i32, usize> = modules.iter().enumerate()
+        .map(|(idx, m)| (m.object_id, idx))
+        .collect();
+    let mut parents: Vec<Vec<usize>> = modules.iter().map(|_| Vec::new()).collect();
+    for (referencing, referenced) in deps.iter() {
+        if let (Some(child), Some(parent)) = (positions.get(referencing), positions.get(referenced)) {
+            parents[*child].push(*parent);
+        }
+    }
+    let mut placed: Vec<bool> = modules.iter().map(|_| false).collect();
+    let mut order = Vec::new();
+    while order.len() < modules.len() {
+        let ready = (0..modules.len())
+            .find(|idx| !placed[*idx] && parents[*idx].iter().all(|p| placed[*p]));
+        let next = match ready {
+            Some(idx) => idx,
+            None => match (0..modules.len()).find(|idx| !placed[*idx]) {
+                Some(idx) => idx,
+                None => break
+            }
+        };
+        placed[next] = true;
+        order.push(next);
+    }
+    let mut slots: Vec<Option<SqlModule>> = modules.into_iter().map(Some).collect();
+    order.into_iter().filter_map(|idx| slots[idx].take()).collect()
+}
+
+pub fn load_sql_modules<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime,
+                                         client: &mut Client<Compat<TcpStream>>) -> Result<Vec<SqlModule>, TransferError> {
+    runtime.block_on(async {
+        let modules = load_modules(progress_fun, client).await?;
+        let deps = match load_module_dependencies(client).await {
+            Ok(deps) => deps,
+            Err(e) => {
+                progress_fun(&format!("Module dependencies are not loaded, modules are exported in creation order, error: {}", e));
+                Vec::new()
+            }
+        };
+        Ok(sort_modules(modules, &deps))
+    })
+}
+
+pub fn modules_to_sql(modules: &Vec<SqlModule>) -> String {
+    let mut res = String::new();
+    for m in modules.iter() {
+        res.push_str(&format!("-- module: {}\t{}\t{}\r\n{}\r\nGO\r\n", &m.type_desc, &m.schema, &m.name, m.definition.trim_end()));
+    }
+    res
+}
+
+pub fn parse_sql_modules(text: &str) -> Result<Vec<SqlModule>, TransferError> {
+    let mut modules = Vec::new();
+    let mut current: Option<SqlModule> = None;
+    let mut lines: Vec<&str> = Vec::new();
+    for ln in text.lines() {
+        if let Some(rest) = ln.strip_prefix("-- module: ") {
+            let parts: Vec<&str> = rest.split('\t').collect();
+            if 3 != parts.len() {
+                return Err(TransferError::from_string(format!(
+                    "Invalid module header: {}", ln)));
+            }
+            current = Some(SqlModule {
+                object_id: 0,
+                schema: parts[1].to_string(),
+                name: parts[2].to_string(),
+                type_desc: parts[0].to_string(),
+                definition: String::new()
+            });
+            lines.clear();
+        } else if "GO" == ln.trim() && current.is_some() {
+            if let Some(mut m) = current.take() {
+                m.definition = lines.join("\r\n");
+                modules.push(m);
+            }
+            lines.clear();
+        } else {
+            lines.push(ln);
+        }
+    }
+    Ok(modules)
+}
+
+pub fn module_exists(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, module: &SqlModule) -> Result<bool, TransferError> {
+    runtime.block_on(async {
+        let mut query = tiberius::Query::new(
+            "select cast(case when object_id(@P1) is null then 0 else 1 end as bit)");
+        query.bind(quote_table(&module.schema, &module.name));
+        let row = query.query(client).await?.into_row().await?;
+        Ok(row.and_then(|r| r.get::<bool, _>(0)).unwrap_or(false))
+    })
+}
+
+pub fn run_sql_modules<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime,
+                                        client: &mut Client<Compat<TcpStream>>) -> Result<(String, String), TransferError> {
+    progress_fun("Scripting views, functions, procedures and triggers ...");
+    let modules = load_sql_modules(progress_fun, runtime, client)?;
+    progress_fun(&format!("Modules found: {}", modules.len()));
+    let modules_filename = "modules.sql".to_string();
+    Ok((modules_filename, modules_to_sql(&modules)))
+}
@@ -0,0 +1,181 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use tiberius::Client;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_util::compat::Compat;
+
+fn column_type(type_name: &str, type_schema: &str, is_user_defined: bool, max_length: i32, precision: i32, scale: i32) -> String {
+    if is_user_defined {
+        return quote_table(type_schema, type_name);
+    }
+    let len = |divisor: i32| {
+        if -1 == max_length { "max".to_string() } else { (max_length / divisor).to_string() }
+    };
+    match type_name.to_lowercase().as_str() {
+        "char" | "varchar" | "binary" | "varbinary" => format!("{}({})", type_name, len(1)),
+        "nchar" | "nvarchar" => format!("{}({})", type_name, len(2)),
+        "decimal" | "numeric" => format!("{}({}, {})", type_name, precision, scale),
+        "time" | "datetime2" | "datetimeoffset" => format!("{}({})", type_name, scale),
+        _ => type_name.to_string()
+    }
+}
+
+pub fn load_table_ddl(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                      schema: &str, table: &str) -> Result<String, TransferError> {
+    runtime.block_on(async {
+        let mut query = tiberius::Query::new("\
+                select
+                    c.name,
+                    type_name(c.user_type_id) as type_name,
+                    schema_name(t.schema_id) as type_schema,
+                    t.is_user_defined,
+                    cast(c.max_length as int) as max_length,
+                    cast(c.precision as int) as precision,
+                    cast(c.scale as int) as scale,
+                    c.is_nullable,
+                    c.is_identity,
+                    cast(ic.seed_value as varchar(64)) as seed_value,
+                    cast(ic.increment_value as varchar(64)) as increment_value,
+                    dc.name as default_name,
+                    dc.definition as default_definition,
+                    cc.definition as computed_definition,
+                    cc.is_persisted,
+                    case
+                        when c.collation_name <> cast(databasepropertyex(db_name(), 'Collation') as sysname)
+                        then c.collation_name
+                    end as collation_name
+                from sys.columns as c
+                join sys.types as t
+                    on t.user_type_id = c.user_type_id
+                left join sys.identity_columns as ic
+                    on ic.object_id = c.object_id
+                    and ic.column_id = c.column_id
+                left join sys.default_constraints as dc
+                    on dc.object_id = c.default_object_id
+                left join sys.computed_columns as cc
+                    on cc.object_id = c.object_id
+                    and cc.column_id = c.column_id
+                where c.object_id = object_id(@P1)
+                order by c.column_id");
+        query.bind(quote_table(schema, table));
+        let rows = query.query(client).await?.into_first_result().await?;
+        let msg = "Table definition select error";
+        let mut lines = Vec::new();
+        for row in rows.iter() {
+            let name: &str = row.get(0).ok_or(TransferError::from_str(msg))?;
+            let computed: Option<&str> = row.get(13);
+            if let Some(definition) = computed {
+                let persisted: bool = row.get(14).unwrap_or(false);
+                lines.push(format!("    {} as {}{}", quote_ident(name), definition,
+                                   if persisted { " persisted" } else { "" }));
+                continue;
+            }
+            let type_name: &str = row.get(1).ok_or(TransferError::from_str(msg))?;
+            let type_schema: &str = row.get(2).ok_or(TransferError::from_str(msg))?;
+            let is_user_defined: bool = row.get(3).ok_or(TransferError::from_str(msg))?;
+            let max_length: i32 = row.get(4).ok_or(TransferError::from_str(msg))?;
+            let precision: i32 = row.get(5).ok_or(TransferError::from_str(msg))?;
+            let scale: i32 = row.get(6).ok_or(TransferError::from_str(msg))?;
+            let nullable: bool = row.get(7).ok_or(TransferError::from_str(msg))?;
+            let identity: bool = row.get(8).ok_or(TransferError::from_str(msg))?;
+            let mut ln = format!("    {} {}", quote_ident(name),
+                                 column_type(type_name, type_schema, is_user_defined, max_length, precision, scale));
+            let collation: Option<&str> = row.get(15);
+            if let Some(collation) = collation {
+                ln.push_str(&format!(" collate {}", collation));
+            }
+            if identity {
+                let seed: &str = row.get(9).unwrap_or("1");
+                let increment: &str = row.get(10).unwrap_or("1");
+                ln.push_str(&format!(" identity({}, {})", seed, increment));
+            }
+            ln.push_str(if nullable { " null" } else { " not null" });
+            let default_name: Option<&str> = row.get(11);
+            let default_definition: Option<&str> = row.get(12);
+            if let (Some(default_name), Some(default_definition)) = (default_name, default_definition) {
+                ln.push_str(&format!(" constraint {} default {}", quote_ident(default_name), default_definition));
+            }
+            lines.push(ln);
+        }
+        if lines.is_empty() {
+            return Err(TransferError::from_string(format!(
+                "Table columns not found, table: {}.{}", schema, table)));
+        }
+        Ok(format!("create table {} (\r\n{}\r\n)\r\n", quote_table(schema, table), lines.join(",\r\n")))
+    })
+}
+
+pub fn table_exists(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                    schema: &str, table: &str) -> Result<bool, TransferError> {
+    runtime.block_on(async {
+        let mut query = tiberius::Query::new(
+            "select cast(case when object_id(@P1, 'U') is null then 0 else 1 end as bit)");
+        query.bind(quote_table(schema, table));
+        let row = query.query(client).await?.into_row().await?;
+        Ok(row.and_then(|r| r.get::<bool, _>(0)).unwrap_or(false))
+    })
+}
+
+pub fn create_schema_if_missing(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                                schema: &str) -> Result<bool, TransferError> {
+    runtime.block_on(async {
+        let mut query = tiberius::Query::new(
+            "select cast(case when schema_id(@P1) is null then 0 else 1 end as bit)");
+        query.bind(schema);
+        let row = query.query(client).await?.into_row().await?;
+        if row.and_then(|r| r.get::<bool, _>(0)).unwrap_or(false) {
+            return Ok(false);
+        }
+        client.execute(format!("create schema {}", quote_ident(schema)), &[]).await?;
+        Ok(true)
+    })
+}
+
+pub fn execute_ddl(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, sql: &str) -> Result<(), TransferError> {
+    runtime.block_on(async {
+        client.execute(sql, &[]).await?;
+        Ok(())
+    })
+}
+
+// drop and create run in one transaction, so the existing table is kept when the create fails
+pub fn recreate_table(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, schema: &str, table: &str,
+                      ddl: &str) -> Result<(), TransferError> {
+    runtime.block_on(async {
+        client.execute("begin transaction", &[]).await?;
+        let res = async {
+            client.execute(format!("drop table {}", quote_table(schema, table)), &[]).await?;
+            client.execute(ddl, &[]).await?;
+            Ok::<(), TransferError>(())
+        }.await;
+        let end = if res.is_ok() { "commit transaction" } else { "if @@trancount > 0 rollback transaction" };
+        client.execute(end, &[]).await?;
+        res
+    })
+}
+
+// returns the entry name and the script text
+pub fn run_table_ddl<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                                      schema: &str, table: &str) -> Result<(String, String), TransferError> {
+    progress_fun(&format!("Creating table definition: {}.{}", schema, table));
+    let ddl_filename = format!("{}.{}.ddl.sql", schema, table);
+    let ddl = load_table_ddl(runtime, client, schema, table)?;
+    Ok((ddl_filename, ddl))
+}
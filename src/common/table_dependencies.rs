@@ -0,0 +1,79 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+#[derive(Default, Clone)]
+pub struct TableDependency {
+    pub child_schema: String,
+    pub child_table: String,
+    pub parent_schema: String,
+    pub parent_table: String,
+}
+
+pub fn load_table_dependencies(cc: &TdsConnConfig, dbname: &str) -> Result<Vec<TableDependency>, TransferError> {
+    let runtime = cc.create_runtime()?;
+    let mut client = cc.open_connection_to_db(&runtime, dbname)?;
+    runtime.block_on(async {
+        let query = tiberius::Query::new("\
+                select distinct
+                    object_schema_name(fk.parent_object_id) as child_schema,
+                    object_name(fk.parent_object_id) as child_table,
+                    object_schema_name(fk.referenced_object_id) as parent_schema,
+                    object_name(fk.referenced_object_id) as parent_table
+                from sys.foreign_keys as fk
+                where fk.is_disabled = 0
+                and fk.parent_object_id <> fk.referenced_object_id");
+        let rows = query.query(&mut client).await?.into_first_result().await?;
+        let mut deps = Vec::new();
+        let msg = "Foreign keys select error";
+        for row in rows.iter() {
+            let child_schema: &str = row.get(0).ok_or(TransferError::from_str(msg))?;
+            let child_table: &str = row.get(1).ok_or(TransferError::from_str(msg))?;
+            let parent_schema: &str = row.get(2).ok_or(TransferError::from_str(msg))?;
+            let parent_table: &str = row.get(3).ok_or(TransferError::from_str(msg))?;
+            deps.push(TableDependency {
+                child_schema: child_schema.to_string(),
+                child_table: child_table.to_string(),
+                parent_schema: parent_schema.to_string(),
+                parent_table: parent_table.to_string(),
+            });
+        }
+        Ok(deps)
+    })
+}
+
+// for every table returns indices of the parent tables from the same list
+pub fn parent_indices(tables: &Vec<(String, String)>, deps: &Vec<TableDependency>) -> Vec<Vec<usize>> {
+    let position = |schema: &str, table: &str| {
+        tables.iter().position(|(s, t)| s == schema && t == table)
+    };
+    let mut parents: Vec<Vec<usize>> = tables.iter().map(|_| Vec::new()).collect();
+    for dep in deps.iter() {
+        let child = match position(&dep.child_schema, &dep.child_table) {
+            Some(idx) => idx,
+            None => continue
+        };
+        let parent = match position(&dep.parent_schema, &dep.parent_table) {
+            Some(idx) => idx,
+            None => continue
+        };
+        if child != parent && !parents[child].contains(&parent) {
+            parents[child].push(parent);
+        }
+    }
+    parents
+}
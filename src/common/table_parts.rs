@@ -0,0 +1,158 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use tiberius::Client;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_util::compat::Compat;
+
+#[derive(Default, Clone, Debug)]
+pub struct TablePart {
+    pub name: String,
+    pub predicate: String,
+}
+
+// leading column of the primary key or of the clustered index,
+// only integer keys can be split into ranges
+fn load_key_column(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>,
+                   schema: &str, table: &str) -> Result<Option<String>, TransferError> {
+    runtime.block_on(async {
+        let mut query = tiberius::Query::new("\
+                select top 1
+                    c.name,
+                    type_name(c.system_type_id) as type_name
+                from sys.indexes as i
+                join sys.index_columns as ic
+                    on ic.object_id = i.object_id
+                    and ic.index_id = i.index_id
+                    and ic.key_ordinal = 1
+                join sys.columns as c
+                    on c.object_id = ic.object_id
+                    and c.column_id = ic.column_id
+                where i.object_id = object_id(@P1)
+                and (i.is_primary_key = 1 or i.type = 1)
+                order by i.is_primary_key desc");
+        query.bind(quote_table(schema, table));
+        let row = match query.query(client).await?.into_row().await? {
+            Some(row) => row,
+            None => return Ok(None)
+        };
+        let msg = "Key column select error";
+        let name: &str = row.get(0).ok_or(TransferError::from_str(msg))?;
+        let type_name: &str = row.get(1).ok_or(TransferError::from_str(msg))?;
+        match type_name.to_lowercase().as_str() {
+            "tinyint" | "smallint" | "int" | "bigint" => Ok(Some(name.to_string())),
+            _ => Ok(None)
+        }
+    })
+}
+
+fn load_key_bounds(runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, schema: &str, table: &str,
+                   column: &str) -> Result<Option<(i64, i64)>, TransferError> {
+    let col = quote_ident(column);
+    let sql = format!("select cast(min({}) as bigint), cast(max({}) as bigint) from {}", col, col, quote_table(schema, table));
+    runtime.block_on(async {
+        let row = match tiberius::Query::new(sql).query(client).await?.into_row().await? {
+            Some(row) => row,
+            None => return Ok(None)
+        };
+        let min: Option<i64> = row.get(0);
+        let max: Option<i64> = row.get(1);
+        Ok(min.zip(max))
+    })
+}
+
+// splits the key range evenly, first part also takes rows with null keys
+// and the last part is open-ended to take rows with keys above the maximum
+fn split_key_range(column: &str, min: i64, max: i64, count: i64) -> Vec<TablePart> {
+    let span = max as i128 - min as i128 + 1;
+    let count = (count as i128).min(span);
+    let step = (span + count - 1) / count;
+    let col = quote_ident(column);
+    let bounds: Vec<i128> = (1..count).map(|i| min as i128 + i * step).filter(|b| *b <= max as i128).collect();
+    let mut parts = Vec::new();
+    for i in 0..=bounds.len() {
+        let predicate = if 0 == i && bounds.is_empty() {
+            String::new()
+        } else if 0 == i {
+            format!("{} < {} or {} is null", col, bounds[0], col)
+        } else if i == bounds.len() {
+            format!("{} >= {}", col, bounds[i - 1])
+        } else {
+            format!("{} >= {} and {} < {}", col, bounds[i - 1], col, bounds[i])
+        };
+        parts.push(TablePart {
+            name: format!("part{:03}", i + 1),
+            predicate
+        });
+    }
+    parts
+}
+
+pub fn plan_table_parts<P: Fn(&str)->()>(progress_fun: &P, runtime: &Runtime, client: &mut Client<Compat<TcpStream>>, schema: &str,
+                                         table: &str, row_count: i64, chunk_rows: i64) -> Result<Vec<TablePart>, TransferError> {
+    let column = match load_key_column(runtime, client, schema, table)? {
+        Some(column) => column,
+        None => {
+            progress_fun("Integer primary key or clustered index not found, table is exported as a single part");
+            return Ok(Vec::new());
+        }
+    };
+    let (min, max) = match load_key_bounds(runtime, client, schema, table, &column)? {
+        Some(bounds) => bounds,
+        None => return Ok(Vec::new())
+    };
+    let count = (row_count + chunk_rows - 1) / chunk_rows;
+    let parts = split_key_range(&column, min, max, count);
+    if parts.len() < 2 {
+        return Ok(Vec::new());
+    }
+    progress_fun(&format!("Splitting table into {} parts by key column: {}", parts.len(), column));
+    Ok(parts)
+}
+
+pub fn parts_to_text(parts: &Vec<TablePart>) -> String {
+    let mut res = String::new();
+    for part in parts.iter() {
+        res.push_str(&format!("{}\t{}\r\n", &part.name, &part.predicate));
+    }
+    res
+}
+
+pub fn parse_parts(text: &str) -> Result<Vec<TablePart>, TransferError> {
+    let mut parts = Vec::new();
+    for ln in text.lines().filter(|ln| !ln.trim().is_empty()) {
+        let (name, predicate) = ln.split_once('\t').ok_or(TransferError::from_string(format!(
+            "Invalid table parts line: {}", ln)))?;
+        parts.push(TablePart {
+            name: name.to_string(),
+            predicate: predicate.to_string()
+        });
+    }
+    Ok(parts)
+}
+
+pub fn combine_predicates(filter: &str, part: &str) -> String {
+    if filter.is_empty() {
+        part.to_string()
+    } else if part.is_empty() {
+        filter.to_string()
+    } else {
+        format!("({}) and ({})", filter, part)
+    }
+}
@@ -20,6 +20,7 @@ pub struct TableWithRowsCount {
     pub table: String,
     pub row_count: i64,
     pub export: bool,
+    pub predicate: String,
 }
 
 impl TableWithRowsCount {
@@ -28,7 +29,8 @@ impl TableWithRowsCount {
             schema: schema.to_string(),
             table: table.to_string(),
             row_count,
-            export: false
+            export: false,
+            predicate: String::new()
         }
     }
 }
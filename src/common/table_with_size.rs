@@ -21,13 +21,26 @@ pub struct TableWithSize {
     pub schema: String,
     pub table: String,
     pub size_bytes: u64,
+    // known only for archives with manifest
+    pub row_count: Option<i64>,
     pub import: bool,
+    // data part names, empty when table data is stored in a single entry
+    pub parts: Vec<String>,
+    // table definition is exported without the data
+    pub schema_only: bool,
 }
 
 impl TableWithSize {
     pub fn new(zip_entry_name: &str, size_bytes: u64) -> Result<Self, TransferError> {
-        let parts = zip_entry_name.split(".").collect::<Vec<&str>>();
-        if !(4 == parts.len() && "bcp" == parts[2] && ("gz" == parts[3] || "zstd" == parts[3])) {
+        let uncompressed_name = zip_entry_name
+            .strip_suffix(".gz")
+            .or_else(|| zip_entry_name.strip_suffix(".zstd"))
+            .unwrap_or(zip_entry_name);
+        let parts = match uncompressed_name.strip_suffix(".bcp") {
+            Some(name) => name.split(".").collect::<Vec<&str>>(),
+            None => Vec::new()
+        };
+        if !(2 == parts.len() || (3 == parts.len() && parts[2].starts_with("part"))) {
             return Err(TransferError::from_string(format!(
                 "Unexpected ZIP entry name: {}", zip_entry_name)));
         }
@@ -35,7 +48,28 @@ impl TableWithSize {
             schema: parts[0].to_string(),
             table: parts[1].to_string(),
             size_bytes,
-            import: false
+            row_count: None,
+            import: false,
+            parts: if 3 == parts.len() { vec!(parts[2].to_string()) } else { Vec::new() },
+            schema_only: false
         })
     }
+
+    pub fn from_manifest(mt: &ManifestTable) -> Self {
+        Self {
+            schema: mt.schema.clone(),
+            table: mt.table.clone(),
+            size_bytes: mt.size_bytes,
+            row_count: if mt.row_count >= 0 { Some(mt.row_count) } else { None },
+            import: false,
+            parts: mt.parts.clone(),
+            schema_only: mt.data_files.is_empty()
+        }
+    }
+
+    pub fn add_part(&mut self, other: &TableWithSize) {
+        self.size_bytes += other.size_bytes;
+        self.parts.extend(other.parts.iter().cloned());
+        self.parts.sort();
+    }
 }
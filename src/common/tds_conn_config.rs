@@ -80,4 +80,18 @@ impl TdsConnConfig {
     pub fn open_connection_to_db(&self, runtime: &Runtime, dbname: &str) -> Result<Client<Compat<TcpStream>>, TransferError> {
         self.open_connection(runtime, dbname)
     }
+
+    // opens the connection on first call and reuses it on subsequent calls
+    pub fn open_cached_connection<'a>(&self, dbname: &str, conn: &'a mut Option<(Runtime, Client<Compat<TcpStream>>)>)
+                                      -> Result<(&'a Runtime, &'a mut Client<Compat<TcpStream>>), TransferError> {
+        if conn.is_none() {
+            let runtime = self.create_runtime()?;
+            let client = self.open_connection_to_db(&runtime, dbname)?;
+            *conn = Some((runtime, client));
+        }
+        match conn.as_mut() {
+            Some((runtime, client)) => Ok((runtime, client)),
+            None => Err(TransferError::from_str("TDS connection error"))
+        }
+    }
 }
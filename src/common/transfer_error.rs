@@ -86,3 +86,9 @@ impl From<std::num::ParseIntError> for TransferError {
         Self::new(&value)
     }
 }
+
+impl From<parquet::errors::ParquetError> for TransferError {
+    fn from(value: parquet::errors::ParquetError) -> Self {
+        Self::new(&value)
+    }
+}
@@ -0,0 +1,186 @@
+/*
+ * Copyright 2024, WiltonDB Software
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::*;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::Read;
+use std::path::Path;
+
+use ed25519_dalek::VerifyingKey;
+use flate2::read::GzDecoder;
+use sha2::Digest;
+use sha2::Sha256;
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+// data entries are hashed after decompression, other entries are hashed as is
+pub(super) fn entry_sha256<R: Read>(reader: R, codec: Option<CompressionCodec>) -> Result<String, TransferError> {
+    let mut hasher = Sha256::new();
+    let mut reader = BufReader::new(reader);
+    match codec {
+        Some(CompressionCodec::Zstd) => {
+            let mut decoder = zstd::Decoder::new(reader)?;
+            io::copy(&mut decoder, &mut hasher)?;
+        },
+        Some(CompressionCodec::Gzip) => {
+            let mut decoder = GzDecoder::new(reader);
+            io::copy(&mut decoder, &mut hasher)?;
+        },
+        _ => {
+            io::copy(&mut reader, &mut hasher)?;
+        }
+    };
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn read_entry_text(zip: &mut ZipArchive<BufReader<File>>, dirname: &str, filename: &str,
+                   key: Option<&EncryptionKey>) -> Result<Option<String>, TransferError> {
+    let entry_name = format!("{}/{}", dirname, filename);
+    let entry = match zip.by_name(&entry_name) {
+        Ok(entry) => entry,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into())
+    };
+    let mut text = String::new();
+    decrypting_reader(entry, key, &entry_name).read_to_string(&mut text)?;
+    Ok(Some(text))
+}
+
+// signature is required when the trusted key is specified,
+// archives without checksums in manifest are only checked to decompress cleanly
+pub(super) fn verify_archive_entries<P: Fn(&str)->()>(progress_fun: &P, zip: &mut ZipArchive<BufReader<File>>, dirname: &str,
+                                                      key: Option<&EncryptionKey>, trusted_key: Option<&VerifyingKey>) -> Result<(), TransferError> {
+    let manifest_text = read_entry_text(zip, dirname, MANIFEST_FILENAME, key)?;
+    if let Some(trusted_key) = trusted_key {
+        let signature_text = read_entry_text(zip, dirname, SIGNATURE_FILENAME, key)?;
+        match (&manifest_text, &signature_text) {
+            (Some(manifest_text), Some(signature_text)) => verify_signature(trusted_key, manifest_text, signature_text)?,
+            _ => return Err(TransferError::from_str("Export file is not signed"))
+        };
+        progress_fun("Signature verified with the trusted key");
+    }
+    let mut expected: HashMap<String, ManifestEntry> = HashMap::new();
+    match manifest_text {
+        Some(text) => {
+            let manifest = Manifest::parse(&text)?;
+            progress_fun(&format!("Archive of database: {}, server: {}, exported at: {}",
+                                  &manifest.source.database, &manifest.source.server, &manifest.exported_at));
+            for me in manifest.entries {
+                // data files stored outside of the export file are not checked
+                if me.external {
+                    progress_fun(&format!("SKIPPED: {}, stored outside of export file", &me.name));
+                    continue;
+                }
+                expected.insert(me.name.clone(), me);
+            }
+        },
+        None => progress_fun("Manifest not found, checksums are not available")
+    };
+    let has_checksums = !expected.is_empty();
+    if !has_checksums {
+        if trusted_key.is_some() {
+            return Err(TransferError::from_str("Signed manifest does not contain entry checksums"));
+        }
+        progress_fun("Checking that all entries can be decompressed ...");
+    }
+
+    let prefix = format!("{}/", dirname);
+    let mut checked = 0;
+    let mut failed = 0;
+    for i in 0..zip.len() {
+        let entry = zip.by_index(i)?;
+        let entry_name = entry.name().to_string();
+        let filename = match entry.name().strip_prefix(&prefix) {
+            Some(filename) => filename.to_string(),
+            None => entry.name().to_string()
+        };
+        if filename.is_empty() || MANIFEST_FILENAME == filename || SIGNATURE_FILENAME == filename {
+            continue;
+        }
+        let size = entry.size();
+        let entry_key = if ENCRYPTION_FILENAME == filename { None } else { key };
+        // CRC of the entry is checked by the ZIP reader when the entry is read till the end
+        let sha256 = match entry_sha256(decrypting_reader(entry, entry_key, &entry_name), data_file_codec(&filename)) {
+            Ok(sha256) => sha256,
+            Err(e) => {
+                progress_fun(&format!("FAILED: {}, read error: {}", &filename, e));
+                failed += 1;
+                expected.remove(&filename);
+                continue;
+            }
+        };
+        checked += 1;
+        if !has_checksums {
+            progress_fun(&format!("OK: {}", &filename));
+            continue;
+        }
+        match expected.remove(&filename) {
+            Some(me) if me.size == size && me.sha256 == sha256 => {
+                progress_fun(&format!("OK: {}", &filename));
+            },
+            Some(me) => {
+                progress_fun(&format!("FAILED: {}, checksum mismatch, expected: {} ({} bytes), actual: {} ({} bytes)",
+                                      &filename, &me.sha256, me.size, &sha256, size));
+                failed += 1;
+            },
+            None => {
+                progress_fun(&format!("FAILED: {}, entry is not listed in manifest", &filename));
+                failed += 1;
+            }
+        }
+    }
+    let mut missing: Vec<&String> = expected.keys().collect();
+    missing.sort();
+    for filename in missing.iter() {
+        progress_fun(&format!("FAILED: {}, entry is missing from export file", filename));
+    }
+    failed += missing.len();
+
+    progress_fun(&format!("Entries checked: {}, failed: {}", checked, failed));
+    if failed > 0 {
+        return Err(TransferError::from_string(format!(
+            "Export file verification failed, failed entries: {}", failed)));
+    }
+    Ok(())
+}
+
+// checks all entries of the export file without connecting to DB
+pub fn run_verify<P: Fn(&str)->()>(progress_fun: &P, file_path: &str, secret: &EncryptionSecret,
+                                   trusted_key_file: &str) -> Result<(), TransferError> {
+    if !Path::new(file_path).exists() {
+        return Err(TransferError::from_string(format!(
+            "Specified file is not found, path: {}", file_path)));
+    }
+    let trusted_key = if trusted_key_file.is_empty() {
+        None
+    } else {
+        Some(load_trusted_key(trusted_key_file)?)
+    };
+    progress_fun(&format!("Verifying file: {}", file_path));
+    let mut zip = ZipArchive::new(BufReader::new(File::open(file_path)?))?;
+    let dirname: String = match zip.file_names().find(|nm| nm.ends_with("/")) {
+        Some(dirname) => dirname.chars().take(dirname.len() - 1).collect(),
+        None => return Err(TransferError::from_str("Directory entry not found in ZIP file"))
+    };
+    let key = archive_key(&mut zip, &dirname, secret)?;
+    verify_archive_entries(progress_fun, &mut zip, &dirname, key.as_ref(), trusted_key.as_ref())?;
+    progress_fun("Export file verified successfully");
+    Ok(())
+}
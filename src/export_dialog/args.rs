@@ -24,7 +24,7 @@ pub struct ExportDialogArgs {
 }
 
 impl ExportDialogArgs {
-    pub fn new(notice: &ui::SyncNotice, conn_config: &TdsConnConfig, dbname: &str, tables: &Vec<TableWithRowsCount>, parent_dir: &str, dest_filename: &str) -> Self {
+    pub fn new(notice: &ui::SyncNotice, conn_config: &TdsConnConfig, dbname: &str, tables: &Vec<TableWithRowsCount>, parent_dir: &str, dest_filename: &str, native_tds: bool) -> Self {
         Self {
             notice_sender: notice.sender(),
             conn_config: conn_config.clone(),
@@ -32,7 +32,23 @@ impl ExportDialogArgs {
                 dbname: dbname.to_string(),
                 tables: tables.clone(),
                 parent_dir: parent_dir.to_string(),
-                dest_filename: dest_filename.to_string()
+                dest_filename: dest_filename.to_string(),
+                native_tds,
+                jobs: 1,
+                stream_compression: false,
+                resume: false,
+                columns: Vec::new(),
+                schema_only: false,
+                export_modules: false,
+                export_security: false,
+                chunk_rows: 0,
+                codec: CompressionCodec::Zstd,
+                compression_level: 1,
+                compression_threads: 3,
+                encryption: EncryptionSecret::default(),
+                signing_key_file: String::new(),
+                data_format: DataFormat::Bcp,
+                data_dir: String::new(),
             },
         }
     }
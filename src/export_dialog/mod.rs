@@ -31,6 +31,9 @@ use clipboard_win::set_clipboard;
 use nwg::NativeUi;
 
 use crate::*;
+use common::CompressionCodec;
+use common::DataFormat;
+use common::EncryptionSecret;
 use common::ExportArgs;
 use common::ExportResult;
 use common::TableWithRowsCount;
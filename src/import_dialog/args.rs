@@ -16,6 +16,7 @@
 
 use super::*;
 
+use std::collections::HashMap;
 
 #[derive(Default)]
 pub struct ImportDialogArgs {
@@ -25,7 +26,7 @@ pub struct ImportDialogArgs {
 }
 
 impl ImportDialogArgs {
-    pub fn new(notice: &ui::SyncNotice, conn_config: &TdsConnConfig, dbname: &str, tables: &Vec<TableWithSize>, import_file: &str, work_dir: &str) -> Self {
+    pub fn new(notice: &ui::SyncNotice, conn_config: &TdsConnConfig, dbname: &str, tables: &Vec<TableWithSize>, import_file: &str, work_dir: &str, native_tds: bool, encryption: &EncryptionSecret) -> Self {
         Self {
             notice_sender: notice.sender(),
             conn_config: conn_config.clone(),
@@ -34,6 +35,18 @@ impl ImportDialogArgs {
                 tables: tables.clone(),
                 import_file: import_file.to_string(),
                 work_dir: work_dir.to_string(),
+                native_tds,
+                jobs: 1,
+                resume: false,
+                create_tables: false,
+                replace_policy: ReplacePolicy::Keep,
+                create_indexes: false,
+                create_modules: false,
+                reseed: true,
+                create_security: false,
+                user_mapping: HashMap::new(),
+                encryption: encryption.clone(),
+                trusted_key_file: String::new(),
             },
         }
     }
@@ -31,8 +31,10 @@ use clipboard_win::set_clipboard;
 use nwg::NativeUi;
 
 use crate::*;
+use common::EncryptionSecret;
 use common::ImportArgs;
 use common::ImportResult;
+use common::ReplacePolicy;
 use common::TableWithSize;
 use common::TdsConnConfig;
 use nwg_ui as ui;
@@ -16,7 +16,9 @@
 
 mod common;
 
+use std::collections::HashMap;
 use std::env;
+use std::fs;
 use std::path::PathBuf;
 use std::process;
 
@@ -25,9 +27,20 @@ use clap::ArgAction;
 use clap::ArgMatches;
 use clap::Command;
 
+use common::ColumnSelection;
+use common::CompressionCodec;
+use common::DataFormat;
+use common::EncryptionSecret;
 use common::ExportArgs;
 use common::ImportArgs;
+use common::ParquetFormat;
+use common::ReplacePolicy;
+use common::SqlFormat;
+use common::TableWithRowsCount;
 use common::TdsConnConfig;
+use common::TextEncoding;
+use common::TextFormat;
+use common::TextQuoting;
 use common::TransferError;
 
 fn main() {
@@ -37,11 +50,11 @@ fn main() {
         .about("Data transfer tool for WiltonDB")
         .arg(Arg::new("command")
             .required(true)
-            .help("Specifies the task to perform, either 'export' or 'import'"))
+            .help("Specifies the task to perform, either 'export', 'import' or 'verify'"))
         .arg(Arg::new("hostname")
             .short('s')
             .long("hostname")
-            .required(true)
+            .required(false)
             .help("Specifies the hostname of the DB to which to connect."))
         .arg(Arg::new("port")
             .short('p')
@@ -78,7 +91,7 @@ fn main() {
         .arg(Arg::new("database")
             .short('d')
             .long("database")
-            .required(true)
+            .required(false)
             .help("Specifies the database to connect to."))
         .arg(Arg::new("check_certificate")
             .short('c')
@@ -104,6 +117,158 @@ fn main() {
             .required(false)
             .action(ArgAction::SetTrue)
             .help("Overwrite existing output file."))
+        .arg(Arg::new("native_tds")
+            .short('t')
+            .long("native_tds")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .help("Use built-in TDS client instead of 'bcp.exe' utility."))
+        .arg(Arg::new("jobs")
+            .short('j')
+            .long("jobs")
+            .required(false)
+            .help("Specifies the number of tables to export or import in parallel, default: 1."))
+        .arg(Arg::new("codec")
+            .long("codec")
+            .required(false)
+            .help("Specifies the compression of exported data files: 'zstd', 'gzip' or 'none', default: 'zstd'."))
+        .arg(Arg::new("compression_level")
+            .long("compression_level")
+            .required(false)
+            .help("Specifies the compression level, 1 to 22 for 'zstd' (default: 1), 0 to 9 for 'gzip' (default: 6)."))
+        .arg(Arg::new("compression_threads")
+            .long("compression_threads")
+            .required(false)
+            .help("Specifies the number of 'zstd' compression threads per table, 0 to compress in the calling thread, default: 3."))
+        .arg(Arg::new("data_format")
+            .long("data_format")
+            .required(false)
+            .help("Specifies the format of exported data: 'bcp', 'csv', 'tsv', 'parquet' or 'sql', default: 'bcp', only 'bcp' data can be imported back."))
+        .arg(Arg::new("text_delimiter")
+            .long("text_delimiter")
+            .required(false)
+            .help("Specifies the field delimiter for text data: a single character or 'comma', 'tab', 'semicolon', 'pipe'."))
+        .arg(Arg::new("text_quoting")
+            .long("text_quoting")
+            .required(false)
+            .help("Specifies which text data values are enclosed in double quotes: 'minimal', 'all' or 'none', default: 'minimal'."))
+        .arg(Arg::new("text_encoding")
+            .long("text_encoding")
+            .required(false)
+            .help("Specifies the encoding of text data: 'utf8', 'utf8bom' or 'utf16', default: 'utf8'."))
+        .arg(Arg::new("text_null")
+            .long("text_null")
+            .required(false)
+            .help("Specifies the text written for NULL values in text data, default: empty."))
+        .arg(Arg::new("no_header")
+            .long("no_header")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .help("Do not write the column names header row into text data."))
+        .arg(Arg::new("row_group_rows")
+            .long("row_group_rows")
+            .required(false)
+            .help("Specifies the maximum number of rows in a row group of Parquet data, default: 100000."))
+        .arg(Arg::new("insert_batch_rows")
+            .long("insert_batch_rows")
+            .required(false)
+            .help("Specifies the number of rows in a single INSERT statement of SQL data, 1 to 1000, default: 100."))
+        .arg(Arg::new("data_dir")
+            .long("data_dir")
+            .required(false)
+            .help("Specifies the directory where exported data files are written instead of the output file, cannot be used with encryption."))
+        .arg(Arg::new("chunk_rows")
+            .long("chunk_rows")
+            .required(false)
+            .help("Splits tables with more rows than specified into multiple data parts by primary key ranges on export."))
+        .arg(Arg::new("stream_compression")
+            .short('z')
+            .long("stream_compression")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .help("Compress bcp output on the fly through a named pipe without writing uncompressed temporary files, TDS and other data formats are always streamed."))
+        .arg(Arg::new("resume")
+            .long("resume")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .help("Continue previously failed export or import skipping the tables that were already processed."))
+        .arg(Arg::new("filters_file")
+            .short('f')
+            .long("filters_file")
+            .required(false)
+            .help("Specifies the path to a file with row filters for exported tables, one 'schema.table: predicate' line per table."))
+        .arg(Arg::new("columns_file")
+            .short('l')
+            .long("columns_file")
+            .required(false)
+            .help("Specifies the path to a file with columns to export, one 'schema.table: col1, col2, -col3' line per table, columns with '-' prefix are excluded."))
+        .arg(Arg::new("schema_only")
+            .long("schema_only")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .help("Export table definitions only, without the data."))
+        .arg(Arg::new("create_tables")
+            .long("create_tables")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .help("Create missing schemas and tables on import using table definitions from the input file."))
+        .arg(Arg::new("replace_policy")
+            .long("replace_policy")
+            .required(false)
+            .help("Specifies what to do with existing tables on import: 'keep', 'truncate' or 'recreate', default: 'keep'."))
+        .arg(Arg::new("create_indexes")
+            .long("create_indexes")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .help("Create indexes, keys, check constraints and foreign keys from the input file after the data is imported."))
+        .arg(Arg::new("modules")
+            .long("modules")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .help("Export views, functions, procedures and triggers, or create them on import after the data is imported."))
+        .arg(Arg::new("security")
+            .long("security")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .help("Export database roles, users, role memberships and permissions, or apply them on import after the data is imported."))
+        .arg(Arg::new("user_mapping")
+            .long("user_mapping")
+            .required(false)
+            .help("Comma-separated list of 'user=login' pairs, specifies target server logins for database users on import."))
+        .arg(Arg::new("skip_reseed")
+            .long("skip_reseed")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .help("Do not reseed identity columns and restart sequences on import using the values recorded on export."))
+        .arg(Arg::new("passphrase")
+            .long("passphrase")
+            .required(false)
+            .conflicts_with_all(["passphrase_env", "passphrase_prompt", "key_file"])
+            .help("Encrypts the output file with the specified passphrase on export, or decrypts the input file on import and verify."))
+        .arg(Arg::new("passphrase_env")
+            .long("passphrase_env")
+            .required(false)
+            .conflicts_with_all(["passphrase", "passphrase_prompt", "key_file"])
+            .help("Specifies the name of an environment variable the passphrase is read from, can be used instead of 'passphrase' option."))
+        .arg(Arg::new("passphrase_prompt")
+            .long("passphrase_prompt")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["passphrase", "passphrase_env", "key_file"])
+            .help("Prompts for the passphrase on the terminal, can be used instead of 'passphrase' option."))
+        .arg(Arg::new("key_file")
+            .long("key_file")
+            .required(false)
+            .conflicts_with_all(["passphrase", "passphrase_env", "passphrase_prompt"])
+            .help("Specifies the path to a key file, or to a file containing the passphrase, used to encrypt or decrypt data file."))
+        .arg(Arg::new("signing_key")
+            .long("signing_key")
+            .required(false)
+            .help("Specifies the path to an Ed25519 private key in PEM format, the manifest of the output file is signed with it on export."))
+        .arg(Arg::new("trusted_key")
+            .long("trusted_key")
+            .required(false)
+            .help("Specifies the path to an Ed25519 public key in PEM format, input files that are not signed with the matching private key are rejected on import and verify."))
         .get_matches();
 
     match run(&args) {
@@ -119,21 +284,42 @@ fn main() {
 
 fn run(args: &ArgMatches) -> Result<(), TransferError> {
     let (cmd, file_path) = check_command(&args)?;
+    // verification does not connect to DB
+    if "verify" == cmd {
+        return run_verify(file_path, &args);
+    }
     let cfg = create_conn_cfg(&args)?;
 
     if "export" == cmd {
-        run_export(cfg, file_path)
+        run_export(cfg, file_path, &args)
     } else if "import" == cmd {
-        run_import(cfg, file_path)
+        run_import(cfg, file_path, &args)
     } else {
         Err(TransferError::from_string(format!("invalid comand name: {}", cmd)))
     }
 }
 
-fn run_export(cfg: TdsConnConfig, output_file_path: PathBuf) -> Result<(), TransferError> {
+fn run_export(cfg: TdsConnConfig, output_file_path: PathBuf, args: &ArgMatches) -> Result<(), TransferError> {
     let progress_fun = |st: &str| {
         println!("{}", st);
     };
+    let native_tds = args.get_one::<bool>("native_tds").map(|v| *v).unwrap_or(false);
+    let jobs = check_jobs(&args)?;
+    let stream_compression = args.get_one::<bool>("stream_compression").map(|v| *v).unwrap_or(false);
+    let resume = args.get_one::<bool>("resume").map(|v| *v).unwrap_or(false);
+    let filters_file = args.get_one::<String>("filters_file").map(|s| s.to_string()).unwrap_or_default();
+    let columns_file = args.get_one::<String>("columns_file").map(|s| s.to_string()).unwrap_or_default();
+    let schema_only = args.get_one::<bool>("schema_only").map(|v| *v).unwrap_or(false);
+    let export_modules = args.get_one::<bool>("modules").map(|v| *v).unwrap_or(false);
+    let export_security = args.get_one::<bool>("security").map(|v| *v).unwrap_or(false);
+    let chunk_rows = check_chunk_rows(&args)?;
+    let codec = check_codec(&args)?;
+    let compression_level = check_compression_level(&args, codec)?;
+    let compression_threads = check_compression_threads(&args)?;
+    let encryption = check_encryption(&args, true)?;
+    let signing_key_file = args.get_one::<String>("signing_key").map(|s| s.to_string()).unwrap_or_default();
+    let data_format = check_data_format(&args)?;
+    let data_dir = args.get_one::<String>("data_dir").map(|s| s.to_string()).unwrap_or_default();
 
     let output_file = output_file_path.to_string_lossy().to_string();
     let output_file_name_ost = output_file_path.file_name().ok_or(TransferError::from_string(format!(
@@ -144,12 +330,30 @@ fn run_export(cfg: TdsConnConfig, output_file_path: PathBuf) -> Result<(), Trans
         "cannot get parent directory from path: {}", &output_file)))?;
     let parent_dir = parent_dir_path.to_string_lossy().to_string();
 
-    let tables = common::load_tables_from_db(&progress_fun, &cfg, &cfg.database)?;
+    let mut tables = common::load_tables_from_db(&progress_fun, &cfg, &cfg.database)?;
+    apply_filters(&filters_file, &mut tables)?;
+    let columns = load_column_selections(&columns_file, &tables)?;
     let eargs = ExportArgs {
         dbname: cfg.database.to_string(),
         tables: tables,
         parent_dir: parent_dir,
         dest_filename: output_file_name.to_string(),
+        native_tds,
+        jobs,
+        stream_compression,
+        resume,
+        columns,
+        schema_only,
+        export_modules,
+        export_security,
+        chunk_rows,
+        codec,
+        compression_level,
+        compression_threads,
+        encryption,
+        signing_key_file,
+        data_format,
+        data_dir,
     };
     let res = common::run_export(&progress_fun, &cfg, &eargs);
     if !res.error.is_empty() {
@@ -159,21 +363,45 @@ fn run_export(cfg: TdsConnConfig, output_file_path: PathBuf) -> Result<(), Trans
     Ok(())
 }
 
-fn run_import(cfg: TdsConnConfig, input_file_path: PathBuf) -> Result<(), TransferError> {
+fn run_import(cfg: TdsConnConfig, input_file_path: PathBuf, args: &ArgMatches) -> Result<(), TransferError> {
     let progress_fun = |st: &str| {
         println!("{}", st);
     };
+    let native_tds = args.get_one::<bool>("native_tds").map(|v| *v).unwrap_or(false);
+    let jobs = check_jobs(&args)?;
+    let resume = args.get_one::<bool>("resume").map(|v| *v).unwrap_or(false);
+    let create_tables = args.get_one::<bool>("create_tables").map(|v| *v).unwrap_or(false);
+    let replace_policy = check_replace_policy(&args)?;
+    let create_indexes = args.get_one::<bool>("create_indexes").map(|v| *v).unwrap_or(false);
+    let create_modules = args.get_one::<bool>("modules").map(|v| *v).unwrap_or(false);
+    let skip_reseed = args.get_one::<bool>("skip_reseed").map(|v| *v).unwrap_or(false);
+    let create_security = args.get_one::<bool>("security").map(|v| *v).unwrap_or(false);
+    let user_mapping = check_user_mapping(&args)?;
+    let encryption = check_encryption(&args, false)?;
+    let trusted_key_file = args.get_one::<String>("trusted_key").map(|s| s.to_string()).unwrap_or_default();
 
     let input_file = input_file_path.to_string_lossy().to_string();
     let dir_path = input_file_path.with_extension("");
     let dir_path_st = dir_path.to_string_lossy().to_string();
 
-    let tables = common::load_tables_from_file(&progress_fun, &input_file)?;
+    let tables = common::load_tables_from_file(&progress_fun, &input_file, &encryption)?;
     let iargs = ImportArgs {
         dbname: cfg.database.to_string(),
         tables: tables,
         import_file: input_file,
         work_dir: dir_path_st,
+        native_tds,
+        jobs,
+        resume,
+        create_tables,
+        replace_policy,
+        create_indexes,
+        create_modules,
+        reseed: !skip_reseed,
+        create_security,
+        user_mapping,
+        encryption,
+        trusted_key_file,
     };
     let res = common::run_import(&progress_fun, &cfg, &iargs);
     if !res.error.is_empty() {
@@ -183,6 +411,16 @@ fn run_import(cfg: TdsConnConfig, input_file_path: PathBuf) -> Result<(), Transf
     Ok(())
 }
 
+fn run_verify(input_file_path: PathBuf, args: &ArgMatches) -> Result<(), TransferError> {
+    let progress_fun = |st: &str| {
+        println!("{}", st);
+    };
+    let encryption = check_encryption(&args, false)?;
+    let trusted_key_file = args.get_one::<String>("trusted_key").map(|s| s.to_string()).unwrap_or_default();
+    let input_file = input_file_path.to_string_lossy().to_string();
+    common::run_verify(&progress_fun, &input_file, &encryption, &trusted_key_file)
+}
+
 fn check_command(args: &ArgMatches) -> Result<(String, PathBuf), TransferError> {
     let command = args.get_one::<String>("command").map(|s| s.to_string()).unwrap_or_default();
     let input_file = args.get_one::<String>("input_file").map(|s| s.to_string()).unwrap_or_default();
@@ -200,7 +438,7 @@ fn check_command(args: &ArgMatches) -> Result<(String, PathBuf), TransferError>
         } else {
             Err(TransferError::from_str("'output_file' option must be specified"))
         }
-    } else if "import" == command {
+    } else if "import" == command || "verify" == command {
         let input_file_path = PathBuf::from(input_file);
         if input_file_path.exists() {
             Ok((command.to_string(), input_file_path))
@@ -208,8 +446,258 @@ fn check_command(args: &ArgMatches) -> Result<(String, PathBuf), TransferError>
             Err(TransferError::from_str("specified input file does not exist"))
         }
     } else {
-        Err(TransferError::from_str("invalid command, either 'export' or 'import' command must be specified"))
+        Err(TransferError::from_str("invalid command, either 'export', 'import' or 'verify' command must be specified"))
+    }
+}
+
+fn apply_filters(filters_file: &str, tables: &mut Vec<TableWithRowsCount>) -> Result<(), TransferError> {
+    if filters_file.is_empty() {
+        return Ok(());
+    }
+    let text = fs::read_to_string(filters_file).map_err(|e| TransferError::from_string(format!(
+        "cannot read filters file, path: {}, message: {}", filters_file, e)))?;
+    for ln in text.lines() {
+        let line = ln.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, predicate) = line.split_once(':').ok_or(TransferError::from_string(format!(
+            "invalid filters file line, expected 'schema.table: predicate', line: {}", line)))?;
+        let name = name.trim();
+        match tables.iter_mut().find(|t| format!("{}.{}", &t.schema, &t.table).eq_ignore_ascii_case(name)) {
+            Some(table) => table.predicate = predicate.trim().to_string(),
+            None => return Err(TransferError::from_string(format!(
+                "table specified in filters file is not found, name: {}", name)))
+        }
+    }
+    Ok(())
+}
+
+fn load_column_selections(columns_file: &str, tables: &Vec<TableWithRowsCount>) -> Result<Vec<ColumnSelection>, TransferError> {
+    let mut res = Vec::new();
+    if columns_file.is_empty() {
+        return Ok(res);
+    }
+    let text = fs::read_to_string(columns_file).map_err(|e| TransferError::from_string(format!(
+        "cannot read columns file, path: {}, message: {}", columns_file, e)))?;
+    for ln in text.lines() {
+        let line = ln.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, list) = line.split_once(':').ok_or(TransferError::from_string(format!(
+            "invalid columns file line, expected 'schema.table: col1, col2, -col3', line: {}", line)))?;
+        let name = name.trim();
+        let table = tables.iter().find(|t| format!("{}.{}", &t.schema, &t.table).eq_ignore_ascii_case(name))
+            .ok_or(TransferError::from_string(format!(
+                "table specified in columns file is not found, name: {}", name)))?;
+        let mut sel = ColumnSelection {
+            schema: table.schema.clone(),
+            table: table.table.clone(),
+            include: Vec::new(),
+            exclude: Vec::new()
+        };
+        for col in list.split(',').map(|c| c.trim()).filter(|c| !c.is_empty()) {
+            match col.strip_prefix('-') {
+                Some(excluded) => sel.exclude.push(excluded.trim().to_string()),
+                None => sel.include.push(col.to_string())
+            }
+        }
+        res.push(sel);
+    }
+    Ok(res)
+}
+
+fn check_replace_policy(args: &ArgMatches) -> Result<ReplacePolicy, TransferError> {
+    let policy = args.get_one::<String>("replace_policy").map(|s| s.to_lowercase()).unwrap_or_default();
+    match policy.as_str() {
+        "" | "keep" => Ok(ReplacePolicy::Keep),
+        "truncate" => Ok(ReplacePolicy::Truncate),
+        "recreate" => Ok(ReplacePolicy::Recreate),
+        _ => Err(TransferError::from_str("'replace_policy' option must be one of 'keep', 'truncate' or 'recreate'"))
+    }
+}
+
+fn check_user_mapping(args: &ArgMatches) -> Result<HashMap<String, String>, TransferError> {
+    let mapping_st = args.get_one::<String>("user_mapping").map(|s| s.to_string()).unwrap_or_default();
+    let mut res = HashMap::new();
+    for pair in mapping_st.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let (user, login) = pair.split_once('=').ok_or(TransferError::from_string(format!(
+            "invalid 'user_mapping' entry, expected 'user=login', entry: {}", pair)))?;
+        res.insert(user.trim().to_string(), login.trim().to_string());
+    }
+    Ok(res)
+}
+
+fn check_jobs(args: &ArgMatches) -> Result<usize, TransferError> {
+    let jobs_st = args.get_one::<String>("jobs").map(|s| s.to_string()).unwrap_or_default();
+    if jobs_st.is_empty() {
+        return Ok(1);
+    }
+    let jobs: usize = jobs_st.parse()?;
+    if 0 == jobs || jobs > 64 {
+        return Err(TransferError::from_str("'jobs' option must be specified with a value between 1 and 64"));
+    }
+    Ok(jobs)
+}
+
+fn check_chunk_rows(args: &ArgMatches) -> Result<i64, TransferError> {
+    let chunk_rows_st = args.get_one::<String>("chunk_rows").map(|s| s.to_string()).unwrap_or_default();
+    if chunk_rows_st.is_empty() {
+        return Ok(0);
+    }
+    let chunk_rows: i64 = chunk_rows_st.parse()?;
+    if chunk_rows <= 0 {
+        return Err(TransferError::from_str("'chunk_rows' option must be specified with a positive value"));
+    }
+    Ok(chunk_rows)
+}
+
+fn check_codec(args: &ArgMatches) -> Result<CompressionCodec, TransferError> {
+    let codec = args.get_one::<String>("codec").map(|s| s.to_lowercase()).unwrap_or_default();
+    match codec.as_str() {
+        "" | "zstd" => Ok(CompressionCodec::Zstd),
+        "gzip" => Ok(CompressionCodec::Gzip),
+        "none" => Ok(CompressionCodec::Uncompressed),
+        _ => Err(TransferError::from_str("'codec' option must be one of 'zstd', 'gzip' or 'none'"))
+    }
+}
+
+fn check_data_format(args: &ArgMatches) -> Result<DataFormat, TransferError> {
+    let format = args.get_one::<String>("data_format").map(|s| s.to_lowercase()).unwrap_or_default();
+    let delimiter = match format.as_str() {
+        "" | "bcp" => return Ok(DataFormat::Bcp),
+        "csv" => ',',
+        "tsv" => '\t',
+        "parquet" => return Ok(DataFormat::Parquet(ParquetFormat {
+            row_group_rows: check_row_group_rows(&args)?
+        })),
+        "sql" => return Ok(DataFormat::Sql(SqlFormat {
+            batch_rows: check_insert_batch_rows(&args)?
+        })),
+        _ => return Err(TransferError::from_str("'data_format' option must be one of 'bcp', 'csv', 'tsv', 'parquet' or 'sql'"))
+    };
+    let delimiter_st = args.get_one::<String>("text_delimiter").map(|s| s.to_string()).unwrap_or_default();
+    let delimiter = match delimiter_st.to_lowercase().as_str() {
+        "" => delimiter,
+        "comma" => ',',
+        "tab" => '\t',
+        "semicolon" => ';',
+        "pipe" => '|',
+        _ => {
+            let mut chars = delimiter_st.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) if '"' != ch && '\r' != ch && '\n' != ch => ch,
+                _ => return Err(TransferError::from_str(
+                    "'text_delimiter' option must be a single character other than a quote or a line break"))
+            }
+        }
+    };
+    let quoting = match args.get_one::<String>("text_quoting").map(|s| s.to_lowercase()).unwrap_or_default().as_str() {
+        "" | "minimal" => TextQuoting::Minimal,
+        "all" => TextQuoting::All,
+        "none" => TextQuoting::Never,
+        _ => return Err(TransferError::from_str("'text_quoting' option must be one of 'minimal', 'all' or 'none'"))
+    };
+    let encoding = match args.get_one::<String>("text_encoding").map(|s| s.to_lowercase()).unwrap_or_default().as_str() {
+        "" | "utf8" => TextEncoding::Utf8,
+        "utf8bom" => TextEncoding::Utf8Bom,
+        "utf16" => TextEncoding::Utf16,
+        _ => return Err(TransferError::from_str("'text_encoding' option must be one of 'utf8', 'utf8bom' or 'utf16'"))
+    };
+    Ok(DataFormat::Text(TextFormat {
+        delimiter,
+        quoting,
+        header: !args.get_one::<bool>("no_header").map(|v| *v).unwrap_or(false),
+        encoding,
+        null_value: args.get_one::<String>("text_null").map(|s| s.to_string()).unwrap_or_default()
+    }))
+}
+
+fn check_row_group_rows(args: &ArgMatches) -> Result<usize, TransferError> {
+    let rows_st = args.get_one::<String>("row_group_rows").map(|s| s.to_string()).unwrap_or_default();
+    if rows_st.is_empty() {
+        return Ok(ParquetFormat::default().row_group_rows);
+    }
+    let rows: usize = rows_st.parse()?;
+    if 0 == rows {
+        return Err(TransferError::from_str("'row_group_rows' option must be specified with a positive value"));
+    }
+    Ok(rows)
+}
+
+fn check_insert_batch_rows(args: &ArgMatches) -> Result<usize, TransferError> {
+    let rows_st = args.get_one::<String>("insert_batch_rows").map(|s| s.to_string()).unwrap_or_default();
+    if rows_st.is_empty() {
+        return Ok(SqlFormat::default().batch_rows);
+    }
+    let rows: usize = rows_st.parse()?;
+    if 0 == rows || rows > 1000 {
+        return Err(TransferError::from_str("'insert_batch_rows' option must be specified with a value between 1 and 1000"));
+    }
+    Ok(rows)
+}
+
+fn check_compression_level(args: &ArgMatches, codec: CompressionCodec) -> Result<i32, TransferError> {
+    let level_st = args.get_one::<String>("compression_level").map(|s| s.to_string()).unwrap_or_default();
+    if level_st.is_empty() {
+        return Ok(codec.default_level());
+    }
+    let level: i32 = level_st.parse()?;
+    let valid = match codec {
+        CompressionCodec::Zstd => (1..=22).contains(&level),
+        CompressionCodec::Gzip => (0..=9).contains(&level),
+        CompressionCodec::Uncompressed => false
+    };
+    if !valid {
+        return Err(TransferError::from_str("'compression_level' option must be between 1 and 22 for 'zstd' and between 0 and 9 for 'gzip'"));
+    }
+    Ok(level)
+}
+
+fn check_compression_threads(args: &ArgMatches) -> Result<u32, TransferError> {
+    let threads_st = args.get_one::<String>("compression_threads").map(|s| s.to_string()).unwrap_or_default();
+    if threads_st.is_empty() {
+        return Ok(3);
+    }
+    let threads: u32 = threads_st.parse()?;
+    if threads > 64 {
+        return Err(TransferError::from_str("'compression_threads' option must be specified with a value between 0 and 64"));
+    }
+    Ok(threads)
+}
+
+fn prompt_passphrase(confirm: bool) -> Result<String, TransferError> {
+    let passphrase = rpassword::prompt_password("Passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(TransferError::from_str("passphrase must not be empty"));
+    }
+    // typo in a passphrase used for export would make the output file unreadable
+    if confirm && rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        return Err(TransferError::from_str("passphrases do not match"));
+    }
+    Ok(passphrase)
+}
+
+fn check_encryption(args: &ArgMatches, confirm: bool) -> Result<EncryptionSecret, TransferError> {
+    let passphrase = args.get_one::<String>("passphrase").map(|s| s.to_string()).unwrap_or_default();
+    let passphrase_env = args.get_one::<String>("passphrase_env").map(|s| s.to_string()).unwrap_or_default();
+    let passphrase_prompt = args.get_one::<bool>("passphrase_prompt").map(|v| *v).unwrap_or(false);
+    let key_file = args.get_one::<String>("key_file").map(|s| s.to_string()).unwrap_or_default();
+    if !key_file.is_empty() {
+        return EncryptionSecret::from_key_file(&key_file);
+    }
+    if !passphrase_env.is_empty() {
+        return match env::var(&passphrase_env) {
+            Ok(passphrase) if !passphrase.is_empty() => Ok(EncryptionSecret::from_passphrase(&passphrase)),
+            _ => Err(TransferError::from_string(format!(
+                "'passphrase_env' option must specify a non-empty environment variable, name: {}", passphrase_env)))
+        };
+    }
+    if passphrase_prompt {
+        return Ok(EncryptionSecret::from_passphrase(&prompt_passphrase(confirm)?));
     }
+    Ok(EncryptionSecret::from_passphrase(&passphrase))
 }
 
 fn create_conn_cfg(args: &ArgMatches) -> Result<TdsConnConfig, TransferError> {